@@ -1,11 +1,17 @@
-#![feature(exit_status_error)]
-
 use std::env;
 use std::path::Path;
 use std::process::Command;
 
 // Example custom build script.
 fn main() {
+    // The migrations are embedded in the binary, so it has to be rebuilt when they change.
+    println!("cargo::rerun-if-changed=migrations");
+
+    // Without the `ffi` feature nothing links against NFMWorld.Library, so the .NET SDK isn't needed.
+    if env::var_os("CARGO_FEATURE_FFI").is_none() {
+        return;
+    }
+
     let workspace = env::var("CARGO_MANIFEST_DIR").unwrap();
     let _ = std::fs::create_dir("./build");
     let path = Path::new(&workspace).join("build");
@@ -36,12 +42,12 @@ fn main() {
 
     let publish = publish.unwrap();
 
-    if let Err(e) = publish.clone().exit_ok() {
+    if !publish.status.success() {
         let stdout = String::from_utf8_lossy(&publish.stdout);
         let stderr = String::from_utf8_lossy(&publish.stderr);
         eprintln!("stdout: {}", stdout);
         eprintln!("stderr: {}", stderr);
-        panic!("Failed to publish NFMWorld.Library: {}", e);
+        panic!("Failed to publish NFMWorld.Library: {}", publish.status);
     }
 
     let bindgen = Command::new("dotnet")
@@ -65,20 +71,21 @@ fn main() {
 
     let bindgen = bindgen.unwrap();
 
-    if let Err(e) = bindgen.clone().exit_ok() {
+    if !bindgen.status.success() {
         let stdout = String::from_utf8_lossy(&bindgen.stdout);
         let stderr = String::from_utf8_lossy(&bindgen.stderr);
         eprintln!("stdout: {}", stdout);
         eprintln!("stderr: {}", stderr);
-        panic!("Failed to publish NFMWorld.RustBindGen: {}", e);
+        panic!("Failed to publish NFMWorld.RustBindGen: {}", bindgen.status);
     }
 
     let bindgen_out = Command::new(format!("{workspace}/build/NFMWorld.RustBindGen"))
         .current_dir(format!("{workspace}/nfm-world"))
         .output()
-        .unwrap()
-        .exit_ok()
         .unwrap();
+    if !bindgen_out.status.success() {
+        panic!("NFMWorld.RustBindGen failed: {}", bindgen_out.status);
+    }
 
     std::fs::write(format!("{workspace}/src/ffi.rs"), bindgen_out.stdout).unwrap();
 
@@ -0,0 +1,92 @@
+//! Fires concurrent requests at a running server and reports throughput and latency.
+//!
+//! Usage: `cargo run --release --example load_test -- <url> [concurrency] [requests] [json body]`
+//!
+//! Requests are GETs unless a JSON body is given, in which case they are POSTs. For example, to hammer a leaderboard:
+//! `cargo run --release --example load_test -- http://localhost:8081/tt/leaderboard 64 5000 '{"stage": {"author": "...", "name": "..."}}'`
+
+use std::{
+    env,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use reqwest::Client;
+
+#[tokio::main]
+async fn main() {
+    let mut args = env::args().skip(1);
+    let Some(url) = args.next() else {
+        eprintln!("usage: load_test <url> [concurrency] [requests] [json body]");
+        std::process::exit(1);
+    };
+    let concurrency: usize = args.next().map(|a| a.parse().expect("invalid concurrency")).unwrap_or(32);
+    let requests: usize = args.next().map(|a| a.parse().expect("invalid request count")).unwrap_or(2000);
+    let body: Option<serde_json::Value> = args.next().map(|a| serde_json::from_str(&a).expect("invalid json body"));
+
+    let client = Client::new();
+    let remaining = Arc::new(AtomicUsize::new(requests));
+    let failures = Arc::new(AtomicUsize::new(0));
+
+    println!("Sending {requests} requests to {url} with {concurrency} workers");
+    let started = Instant::now();
+
+    let workers: Vec<_> = (0..concurrency)
+        .map(|_| {
+            let (client, url, body) = (client.clone(), url.clone(), body.clone());
+            let (remaining, failures) = (remaining.clone(), failures.clone());
+            tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                // Each worker keeps taking requests off the shared counter until it runs out.
+                while remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok() {
+                    let request = match &body {
+                        Some(body) => client.post(&url).json(body),
+                        None => client.get(&url),
+                    };
+                    let sent = Instant::now();
+                    match request.send().await {
+                        Ok(res) if !res.status().is_server_error() => {
+                            // Read the whole body so the timing covers the full response.
+                            if res.bytes().await.is_err() {
+                                failures.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                        _ => {
+                            failures.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    latencies.push(sent.elapsed());
+                }
+                latencies
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(requests);
+    for worker in workers {
+        latencies.extend(worker.await.expect("worker panicked"));
+    }
+    let elapsed = started.elapsed();
+    latencies.sort();
+
+    let percentile = |p: f64| -> Duration {
+        if latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        latencies[((latencies.len() - 1) as f64 * p).round() as usize]
+    };
+
+    println!("Completed {} requests in {:.2?}", latencies.len(), elapsed);
+    println!("Failures: {}", failures.load(Ordering::Relaxed));
+    println!("Throughput: {:.1} req/s", latencies.len() as f64 / elapsed.as_secs_f64());
+    println!(
+        "Latency: p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        percentile(1.0)
+    );
+}
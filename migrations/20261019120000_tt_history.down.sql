@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_time_trial_history_best;
+DROP INDEX IF EXISTS idx_time_trial_history_time_trial_id;
+DROP TABLE IF EXISTS public.time_trial_history;
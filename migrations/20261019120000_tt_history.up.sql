@@ -0,0 +1,19 @@
+-- Every accepted time trial upload is kept here. time_trials keeps pointing at the best run
+-- for each user/car/stage; the matching history entry is marked with is_best.
+
+CREATE TABLE public.time_trial_history (
+    id uuid PRIMARY KEY NOT NULL,
+    time_trial_id uuid NOT NULL REFERENCES public.time_trials(id) ON DELETE CASCADE,
+    tt_version INTEGER NOT NULL,
+    total_ticks INTEGER NOT NULL,
+    is_best BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_time_trial_history_time_trial_id ON public.time_trial_history(time_trial_id, created_at);
+CREATE UNIQUE INDEX idx_time_trial_history_best ON public.time_trial_history(time_trial_id) WHERE is_best;
+
+-- Seed the history with the runs that are already stored
+INSERT INTO public.time_trial_history (id, time_trial_id, tt_version, total_ticks, is_best, created_at)
+SELECT gen_random_uuid(), id, tt_version, total_ticks, TRUE, created_at
+FROM public.time_trials;
@@ -0,0 +1,4 @@
+DROP INDEX IF EXISTS idx_archive_items_legacy_id;
+
+ALTER TABLE public.archive_items
+    DROP COLUMN legacy_id;
@@ -0,0 +1,12 @@
+-- Items used to be identified by a UUID, which was dropped in 20260504163726_item_id_rm in favour of (author, name).
+-- The UUID also served as the item's filename, so it can be recovered from the path. It is kept only so that
+-- clients which still send UUIDs can be resolved to the (author, name) key.
+
+ALTER TABLE public.archive_items
+    ADD COLUMN legacy_id uuid;
+
+UPDATE public.archive_items
+SET legacy_id = substring(path from '([0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12})\.txt$')::uuid
+WHERE path ~ '[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\.txt$';
+
+CREATE UNIQUE INDEX idx_archive_items_legacy_id ON public.archive_items(legacy_id);
@@ -0,0 +1,9 @@
+DROP INDEX IF EXISTS idx_time_trials_invalidated_by_run;
+
+ALTER TABLE public.time_trials
+    DROP COLUMN invalidated_by_run,
+    DROP COLUMN invalid_reason,
+    DROP COLUMN invalidated_at,
+    DROP COLUMN backend_version;
+
+DROP TABLE IF EXISTS public.tt_revalidation_runs;
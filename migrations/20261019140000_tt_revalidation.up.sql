@@ -0,0 +1,20 @@
+-- Stored ghosts are re-simulated whenever the simulation backend's version changes. Runs that no longer reproduce
+-- are marked invalid rather than deleted, and are left off leaderboards.
+
+CREATE TABLE public.tt_revalidation_runs (
+    id SERIAL PRIMARY KEY,
+    backend_version INTEGER NOT NULL,
+    started_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMP WITHOUT TIME ZONE,
+    checked INTEGER NOT NULL DEFAULT 0,
+    invalidated INTEGER NOT NULL DEFAULT 0
+);
+
+-- backend_version is the version the run was last validated with; NULL for runs stored before it was recorded.
+ALTER TABLE public.time_trials
+    ADD COLUMN backend_version INTEGER,
+    ADD COLUMN invalidated_at TIMESTAMP WITHOUT TIME ZONE,
+    ADD COLUMN invalid_reason TEXT,
+    ADD COLUMN invalidated_by_run INTEGER REFERENCES public.tt_revalidation_runs(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_time_trials_invalidated_by_run ON public.time_trials(invalidated_by_run);
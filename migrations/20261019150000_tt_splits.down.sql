@@ -0,0 +1,2 @@
+ALTER TABLE public.time_trials
+    DROP COLUMN checkpoint_ticks;
@@ -0,0 +1,5 @@
+-- Tick count at which the best run of each time trial passed each checkpoint, in order.
+-- NULL when the backend didn't report splits for the run.
+
+ALTER TABLE public.time_trials
+    ADD COLUMN checkpoint_ticks INTEGER[];
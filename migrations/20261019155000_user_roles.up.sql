@@ -0,0 +1,8 @@
+-- Roles are kept out of the users table so that adding one doesn't change every query on users.
+-- Admins are appointed by hand: INSERT INTO user_roles (user_id, role) VALUES (<id>, 'admin');
+CREATE TABLE public.user_roles (
+    user_id INTEGER NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    role TEXT NOT NULL,
+    granted_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, role)
+);
@@ -0,0 +1,7 @@
+DROP TABLE IF EXISTS public.tt_event_results;
+DROP INDEX IF EXISTS idx_tt_event_entries_history_id;
+DROP TABLE IF EXISTS public.tt_event_entries;
+DROP TABLE IF EXISTS public.tt_event_cars;
+DROP INDEX IF EXISTS idx_tt_events_window;
+DROP INDEX IF EXISTS idx_tt_events_stage;
+DROP TABLE IF EXISTS public.tt_events;
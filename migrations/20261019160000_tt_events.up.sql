@@ -0,0 +1,58 @@
+-- Time trial events: a stage, a set of allowed cars and a time window. Only runs uploaded inside the window count.
+CREATE TABLE public.tt_events (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    stage_author TEXT NOT NULL,
+    stage_name TEXT NOT NULL,
+    starts_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    ends_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    created_by INTEGER REFERENCES public.users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    -- Set once the results have been copied into tt_event_results
+    frozen_at TIMESTAMP WITHOUT TIME ZONE,
+    FOREIGN KEY (stage_author, stage_name) REFERENCES public.archive_items(author, name) ON DELETE CASCADE,
+    CHECK (ends_at > starts_at)
+);
+
+CREATE INDEX idx_tt_events_stage ON public.tt_events(stage_author, stage_name);
+CREATE INDEX idx_tt_events_window ON public.tt_events(starts_at, ends_at);
+
+CREATE TABLE public.tt_event_cars (
+    event_id INTEGER NOT NULL REFERENCES public.tt_events(id) ON DELETE CASCADE,
+    car_author TEXT NOT NULL,
+    car_name TEXT NOT NULL,
+    PRIMARY KEY (event_id, car_author, car_name),
+    FOREIGN KEY (car_author, car_name) REFERENCES public.archive_items(author, name) ON DELETE CASCADE
+);
+
+-- Each user's best run during an event. history_id points at the run's ghost, which is kept for as long as the
+-- entry exists.
+CREATE TABLE public.tt_event_entries (
+    event_id INTEGER NOT NULL REFERENCES public.tt_events(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    car_author TEXT NOT NULL,
+    car_name TEXT NOT NULL,
+    history_id uuid REFERENCES public.time_trial_history(id) ON DELETE SET NULL,
+    tt_version INTEGER NOT NULL,
+    total_ticks INTEGER NOT NULL,
+    set_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (event_id, user_id)
+);
+
+CREATE INDEX idx_tt_event_entries_history_id ON public.tt_event_entries(history_id);
+
+-- Final standings, copied from tt_event_entries when the event ends. Usernames are copied too, so the results read
+-- as they did at the time, and stay in place when the account is deleted.
+CREATE TABLE public.tt_event_results (
+    event_id INTEGER NOT NULL REFERENCES public.tt_events(id) ON DELETE CASCADE,
+    rank INTEGER NOT NULL,
+    user_id INTEGER REFERENCES public.users(id) ON DELETE SET NULL,
+    username TEXT NOT NULL,
+    car_author TEXT NOT NULL,
+    car_name TEXT NOT NULL,
+    history_id uuid,
+    tt_version INTEGER NOT NULL,
+    total_ticks INTEGER NOT NULL,
+    set_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+    PRIMARY KEY (event_id, rank)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE public.time_trials
+    DROP COLUMN flagged_at;
+
+DROP TABLE public.tt_flags;
@@ -0,0 +1,21 @@
+-- Runs that passed simulation but tripped a plausibility check wait here for a moderator.
+CREATE TABLE public.tt_flags (
+    id SERIAL PRIMARY KEY,
+    time_trial_id uuid NOT NULL REFERENCES public.time_trials(id) ON DELETE CASCADE,
+    -- The flagged run; its ghost is kept until the flag is reviewed
+    history_id uuid REFERENCES public.time_trial_history(id) ON DELETE SET NULL,
+    total_ticks INTEGER NOT NULL,
+    reasons TEXT[] NOT NULL,
+    flagged_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    reviewed_at TIMESTAMP WITHOUT TIME ZONE,
+    reviewed_by INTEGER REFERENCES public.users(id) ON DELETE SET NULL,
+    approved BOOLEAN,
+    review_note TEXT
+);
+
+CREATE INDEX idx_tt_flags_pending ON public.tt_flags(flagged_at) WHERE reviewed_at IS NULL;
+CREATE INDEX idx_tt_flags_history_id ON public.tt_flags(history_id);
+
+-- Set while the run a time trial currently points at is awaiting review. Such time trials are left off leaderboards.
+ALTER TABLE public.time_trials
+    ADD COLUMN flagged_at TIMESTAMP WITHOUT TIME ZONE;
@@ -0,0 +1,17 @@
+-- Time trials removed by their owner or by a moderator. The time trial itself is deleted, so what it was is copied
+-- here. reason is NULL for withdrawals by the owner.
+CREATE TABLE public.tt_removals (
+    id SERIAL PRIMARY KEY,
+    time_trial_id uuid NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    car_author TEXT NOT NULL,
+    car_name TEXT NOT NULL,
+    stage_author TEXT NOT NULL,
+    stage_name TEXT NOT NULL,
+    total_ticks INTEGER NOT NULL,
+    removed_by INTEGER REFERENCES public.users(id) ON DELETE SET NULL,
+    reason TEXT,
+    removed_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_tt_removals_user_id ON public.tt_removals(user_id);
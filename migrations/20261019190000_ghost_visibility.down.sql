@@ -0,0 +1,2 @@
+DROP TABLE public.user_follows;
+DROP TABLE public.user_settings;
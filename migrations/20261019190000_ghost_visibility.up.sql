@@ -0,0 +1,17 @@
+-- Per-user settings, kept out of the users table for the same reason as user_roles. A user without a row has the
+-- defaults.
+CREATE TABLE public.user_settings (
+    user_id INTEGER PRIMARY KEY REFERENCES public.users(id) ON DELETE CASCADE,
+    -- Who may download the user's ghosts: 'public', 'friends' (users they follow who follow them back) or 'private'
+    ghost_visibility TEXT NOT NULL DEFAULT 'public' CHECK (ghost_visibility IN ('public', 'friends', 'private'))
+);
+
+CREATE TABLE public.user_follows (
+    follower_id INTEGER NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    followee_id INTEGER NOT NULL REFERENCES public.users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (follower_id, followee_id),
+    CHECK (follower_id <> followee_id)
+);
+
+CREATE INDEX idx_user_follows_followee_id ON public.user_follows(followee_id);
@@ -0,0 +1,3 @@
+ALTER TABLE public.tt_flags
+    DROP COLUMN checkpoint_ticks,
+    DROP COLUMN backend_version;
@@ -0,0 +1,5 @@
+-- A flagged run that beats the user's best only replaces it once approved, so the flag keeps what's needed to make
+-- it the best then. Flags from before this don't have it.
+ALTER TABLE public.tt_flags
+    ADD COLUMN backend_version INTEGER,
+    ADD COLUMN checkpoint_ticks INTEGER[];
@@ -24,19 +24,19 @@ impl From<std::io::Error> for IndexError {
 }
 
 pub async fn index_archive(state: ThreadSafeState) -> Result<(), IndexError> {
-    state.lock().await.index_state = IndexState::Regenerating;
+    state.index_state.set(IndexState::Regenerating);
 
     index_cars(state.clone()).await?;
     index_stages(state.clone()).await?;
     index_stage_pieces(state.clone()).await?;
     index_wheels(state.clone()).await?;
 
-    state.lock().await.index_state = IndexState::Healthy;
+    state.index_state.set(IndexState::Healthy);
     Ok(())
 }
 
 async fn index_cars(state: ThreadSafeState) -> Result<(), IndexError>  {
-    let base_path = format!("{}/cars", state.lock().await.config.filestore);
+    let base_path = format!("{}/cars", state.config.filestore);
     let mut dirs = read_dir(base_path).await?;
     
     while let Some(e) = dirs.next_entry().await? {
@@ -14,6 +14,23 @@ pub struct GenericArchiveFileInfo {
     pub tags: Vec<String>
 }
 
+/// Items are identified by their author and name; this is the primary key of `archive_items`.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ArchiveItemKey {
+    pub author: String,
+    pub name: String,
+}
+
+/// How a client refers to an item in a request. Either the (author, name) key, or the UUID
+/// the item had before items were keyed by author and name. The latter is only kept for
+/// compatibility with older clients; see `ArchiveItem::get_key_by_legacy_id`.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum ArchiveItemRef {
+    Key(ArchiveItemKey),
+    LegacyId(String),
+}
+
 #[derive(serde::Deserialize, PartialEq, Eq)]
 pub enum ArchiveItemType {
     Car = 0,
@@ -32,6 +49,13 @@ impl ToString for ArchiveItemType {
     }
 }
 impl ArchiveItemType {
+    pub const ALL: [ArchiveItemType; 4] = [
+        ArchiveItemType::Car,
+        ArchiveItemType::Stage,
+        ArchiveItemType::StagePiece,
+        ArchiveItemType::Wheel
+    ];
+
     pub fn dir_name(&self) -> String {
         format!("{}s", self.to_string())
     }
@@ -52,14 +76,7 @@ impl TryFrom<&str> for ArchiveItemType {
 
 pub fn ensure_default_dirs_exist(path: &str) -> io::Result<()>
 {
-    const TYPES: [ArchiveItemType; 4] = [
-        ArchiveItemType::Car,
-        ArchiveItemType::Stage,
-        ArchiveItemType::StagePiece,
-        ArchiveItemType::Wheel
-    ];
-
-    for t in TYPES {
+    for t in ArchiveItemType::ALL {
         let name = t.dir_name();
         let fullpath = format!("{path}/{name}");
 
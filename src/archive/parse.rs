@@ -63,6 +63,17 @@ pub fn parse_line(line: String) -> Result<Option<(String, Vec<String>)>, Archive
     Ok(Some((attr, parts)))
 }
 
+/// Counts the checkpoints in a stage file, i.e. its chk(...) lines.
+pub fn count_checkpoints(content: &str) -> Result<i32, ArchiveFileParseError> {
+    let mut count = 0;
+    for line in content.split("\n").map(|x| x.trim().to_ascii_lowercase()) {
+        if let Some((attr, _)) = parse_line(line)? && attr == "chk" {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
 pub fn parse_file(content: String, expect_soundtrack: bool) -> Result<GenericArchiveFileInfo, ArchiveFileParseError> {
     let lines = content.split("\n").map(|x| x.to_ascii_lowercase());
     let mut name: Option<String> = None;
@@ -1,19 +1,474 @@
-use serde::Deserialize;
+use std::{env, fmt::Display, path::PathBuf};
 
-#[derive(Deserialize, Clone)]
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::tt::backend::BackendKind;
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     pub port: u16,
+    /// Port for `/metrics`, which is served on a listener of its own so it can be kept off the public network.
+    /// Unset disables it.
+    #[serde(deserialize_with = "off_or")]
+    pub metrics_port: Option<u16>,
     pub filestore: String,
-    pub discord: DiscordConfig
+    /// "text" for people, "json" for log collectors.
+    pub log_format: LogFormat,
+    pub discord: DiscordConfig,
+    pub database: DatabaseConfig,
+    pub tt: TimeTrialConfig
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            port: 8081,
+            metrics_port: None,
+            filestore: "/var/www".to_string(),
+            log_format: LogFormat::Text,
+            discord: DiscordConfig::default(),
+            database: DatabaseConfig::default(),
+            tt: TimeTrialConfig::default()
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    Json,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
 pub struct DiscordConfig {
     pub client_id: i64,
     pub client_secret: String
 }
 
-pub fn load_config() -> Config {
-    let file = std::fs::read_to_string("config.toml").unwrap();
-    toml::from_str::<Config>(&file).unwrap()
-}
\ No newline at end of file
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Upper bound on open connections. A request holds at most one while it runs its queries.
+    pub max_connections: u32,
+    /// Connections kept open even when idle.
+    pub min_connections: u32,
+    /// How long a request waits for a free connection before it fails.
+    pub acquire_timeout_secs: u64,
+    /// Statements running longer than this are cancelled by Postgres. Unset leaves the database's own setting.
+    /// Migrations are not subject to it.
+    #[serde(deserialize_with = "off_or")]
+    pub statement_timeout_ms: Option<u64>,
+    /// "apply" runs pending migrations at startup; "check" refuses to start while any are pending.
+    pub migrations: MigrationMode
+}
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout_secs: 30,
+            statement_timeout_ms: None,
+            migrations: MigrationMode::Apply
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationMode {
+    Apply,
+    Check,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeTrialConfig {
+    /// Time trials recorded with a replay version below this are kept, but left off leaderboards.
+    pub min_leaderboard_version: i32,
+    /// How many superseded runs are kept per user/car/stage, on top of the best one.
+    pub history_max_entries: i64,
+    /// Superseded runs older than this are removed. Unset keeps them regardless of age.
+    #[serde(deserialize_with = "off_or")]
+    pub history_max_age_days: Option<i32>,
+    /// Number of simulations that can run at once. Defaults to the number of CPUs.
+    pub simulation_workers: usize,
+    /// Number of simulations that can wait for a worker before uploads are turned away.
+    pub simulation_queue_size: usize,
+    /// How long an upload waits for its simulation, including time spent queued.
+    pub simulation_timeout_secs: u64,
+    /// "native" (NFMWorld.Library, needs the `ffi` feature) or "fake", which accepts any well-formed file and is only
+    /// for development. Defaults to native.
+    pub backend: BackendKind,
+    pub plausibility: PlausibilityConfig
+}
+impl Default for TimeTrialConfig {
+    fn default() -> Self {
+        TimeTrialConfig {
+            min_leaderboard_version: 0,
+            history_max_entries: 50,
+            history_max_age_days: None,
+            simulation_workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            simulation_queue_size: 16,
+            simulation_timeout_secs: 30,
+            backend: BackendKind::default(),
+            plausibility: PlausibilityConfig::default()
+        }
+    }
+}
+
+/// Heuristics applied to runs that simulate correctly. A run that trips any of them is held for review.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlausibilityConfig {
+    /// Runs that beat the record for their car and stage by more than this percentage are flagged.
+    /// On by default; "off" disables the check.
+    #[serde(deserialize_with = "off_or")]
+    pub max_record_improvement_percent: Option<f64>,
+    /// Runs that take fewer ticks than this per checkpoint of the stage are flagged. Unset disables the check.
+    #[serde(deserialize_with = "off_or")]
+    pub min_ticks_per_checkpoint: Option<i32>
+}
+impl Default for PlausibilityConfig {
+    fn default() -> Self {
+        PlausibilityConfig {
+            max_record_improvement_percent: Some(10.0),
+            min_ticks_per_checkpoint: None
+        }
+    }
+}
+
+/// Given for an optional setting, unsets it. Leaving a setting out keeps its default, so this is the only way to turn
+/// off one that's on by default.
+const OFF: &str = "off";
+
+/// Deserializes an optional setting that may be given as `OFF`.
+fn off_or<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::de::DeserializeOwned,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(s) if s == OFF => Ok(None),
+        value => value.try_into().map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Settings are read from the environment under this prefix. Nested keys are separated by a double underscore,
+/// e.g. `NFMW_TT__HISTORY_MAX_ENTRIES`.
+const ENV_PREFIX: &str = "NFMW_";
+/// Names the config file, unless `--config` is given on the command line.
+const CONFIG_PATH_VAR: &str = "NFMW_CONFIG";
+/// Appended to a setting's variable to read its value from a file instead, e.g. a Docker secret.
+const FILE_SUFFIX: &str = "_FILE";
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Env(String, String),
+    Invalid(Vec<String>)
+}
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            Self::Io(path, e) => write!(f, "failed to read {}: {e}", path.display()),
+            Self::Parse(path, e) => write!(f, "failed to parse {}: {e}", path.display()),
+            Self::Env(var, e) => write!(f, "invalid {var}: {e}"),
+            Self::Invalid(problems) => write!(f, "invalid configuration:\n  {}", problems.join("\n  "))
+        }
+    }
+}
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Lists everything wrong with the settings, rather than stopping at the first problem.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        if self.port == 0 {
+            problems.push("port must not be 0".to_string());
+        }
+        if self.metrics_port == Some(0) {
+            problems.push("metrics_port must not be 0; leave it unset to disable metrics".to_string());
+        }
+        if self.metrics_port == Some(self.port) {
+            problems.push("metrics_port must differ from port".to_string());
+        }
+        if self.filestore.trim().is_empty() {
+            problems.push("filestore must not be empty".to_string());
+        }
+        if self.database.max_connections < 1 {
+            problems.push("database.max_connections must be at least 1".to_string());
+        }
+        if self.database.min_connections > self.database.max_connections {
+            problems.push("database.min_connections must not exceed database.max_connections".to_string());
+        }
+        if self.database.acquire_timeout_secs < 1 {
+            problems.push("database.acquire_timeout_secs must be at least 1".to_string());
+        }
+        if self.database.statement_timeout_ms == Some(0) {
+            problems.push("database.statement_timeout_ms must be at least 1; leave it unset for no timeout".to_string());
+        }
+        if self.tt.history_max_entries < 0 {
+            problems.push("tt.history_max_entries must not be negative".to_string());
+        }
+        if self.tt.history_max_age_days.is_some_and(|days| days < 1) {
+            problems.push("tt.history_max_age_days must be at least 1".to_string());
+        }
+        if self.tt.simulation_workers < 1 {
+            problems.push("tt.simulation_workers must be at least 1".to_string());
+        }
+        if self.tt.simulation_timeout_secs < 1 {
+            problems.push("tt.simulation_timeout_secs must be at least 1".to_string());
+        }
+        if self.tt.plausibility.max_record_improvement_percent.is_some_and(|p| !(p > 0.0 && p < 100.0)) {
+            problems.push("tt.plausibility.max_record_improvement_percent must be between 0 and 100".to_string());
+        }
+        if self.tt.plausibility.min_ticks_per_checkpoint.is_some_and(|ticks| ticks < 0) {
+            problems.push("tt.plausibility.min_ticks_per_checkpoint must not be negative".to_string());
+        }
+
+        if problems.is_empty() { Ok(()) } else { Err(ConfigError::Invalid(problems)) }
+    }
+}
+
+/// Builds the config from its defaults, then the config file, then `NFMW_*` environment variables.
+/// The file is taken from `--config <path>`, then `NFMW_CONFIG`; `config.toml` is used if it exists.
+pub fn load_config() -> Result<Config, ConfigError> {
+    build_config(read_config_file()?, env::vars())
+}
+
+/// Layers the config file, then the `NFMW_*` variables among `vars`, over the defaults and validates the result.
+fn build_config(file: Option<Value>, vars: impl IntoIterator<Item = (String, String)>) -> Result<Config, ConfigError> {
+    let mut config = Value::try_from(Config::default())
+        .expect("default config is serializable");
+
+    if let Some(file) = file {
+        merge(&mut config, file);
+    }
+    apply_env_overrides(&mut config, vars)?;
+
+    // Deserialized from text rather than the value itself so that type errors point at the offending setting.
+    let merged = toml::to_string(&config).expect("config is serializable");
+    let config = toml::from_str::<Config>(&merged)
+        .map_err(|e| ConfigError::Invalid(vec![e.to_string().trim_end().to_string()]))?;
+    config.validate()?;
+    Ok(config)
+}
+
+fn read_config_file() -> Result<Option<Value>, ConfigError> {
+    let mut args = env::args().skip(1);
+    let explicit = loop {
+        match args.next().as_deref() {
+            Some("--config" | "-c") => break args.next().map(PathBuf::from),
+            Some(arg) if arg.starts_with("--config=") => break Some(PathBuf::from(&arg["--config=".len()..])),
+            Some(_) => continue,
+            None => break env::var_os(CONFIG_PATH_VAR).map(PathBuf::from),
+        }
+    };
+
+    // Only an explicitly named file has to exist; otherwise the defaults and environment are enough.
+    let path = match explicit {
+        Some(path) => path,
+        None if std::fs::exists(DEFAULT_CONFIG_PATH).unwrap_or(false) => PathBuf::from(DEFAULT_CONFIG_PATH),
+        None => return Ok(None),
+    };
+    let file = std::fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+    let table = toml::from_str::<Table>(&file).map_err(|e| ConfigError::Parse(path, e))?;
+    Ok(Some(Value::Table(table)))
+}
+
+/// Overlays `layer` onto `base`, descending into tables present in both.
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Table(base), Value::Table(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+fn apply_env_overrides(config: &mut Value, vars: impl IntoIterator<Item = (String, String)>) -> Result<(), ConfigError> {
+    let mut vars: Vec<(String, String)> = vars.into_iter().filter(|(var, _)| var.starts_with(ENV_PREFIX) && var != CONFIG_PATH_VAR).collect();
+    // Sorted so that a setting given both directly and as a file is caught regardless of environment order.
+    vars.sort();
+
+    for (var, raw) in &vars {
+        let (name, raw) = match var.strip_suffix(FILE_SUFFIX) {
+            Some(name) => {
+                if vars.iter().any(|(other, _)| other == name) {
+                    return Err(ConfigError::Env(var.clone(), format!("{name} is also set; use only one of them")));
+                }
+                let contents = std::fs::read_to_string(raw)
+                    .map_err(|e| ConfigError::Env(var.clone(), format!("failed to read {raw}: {e}")))?;
+                // Secret files usually end with a newline that isn't part of the value.
+                (name, contents.trim_end_matches(['\r', '\n']).to_string())
+            }
+            None => (var.as_str(), raw.clone()),
+        };
+
+        let path: Vec<String> = name[ENV_PREFIX.len()..].split("__").map(str::to_lowercase).collect();
+        set_override(config, &path, &raw).map_err(|e| ConfigError::Env(var.clone(), e))?;
+    }
+    Ok(())
+}
+
+fn set_override(config: &mut Value, path: &[String], raw: &str) -> Result<(), String> {
+    let (key, parents) = path.split_last().ok_or("empty setting name")?;
+    let mut table = config.as_table_mut().expect("config is a table");
+    for (i, parent) in parents.iter().enumerate() {
+        table = table
+            .get_mut(parent)
+            .and_then(Value::as_table_mut)
+            .ok_or_else(|| format!("unknown section {}", path[..=i].join(".")))?;
+    }
+
+    // Parse the value as whatever type the setting already has. Unset optional settings are guessed from the value.
+    // `OFF` is passed through as-is, and rejected when deserializing if the setting isn't optional.
+    let value = match table.get(key) {
+        _ if raw == OFF => Value::String(OFF.to_string()),
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        Some(Value::Integer(_)) => Value::Integer(raw.parse().map_err(|_| format!("expected an integer, got {raw:?}"))?),
+        Some(Value::Float(_)) => Value::Float(raw.parse().map_err(|_| format!("expected a number, got {raw:?}"))?),
+        Some(Value::Boolean(_)) => Value::Boolean(raw.parse().map_err(|_| format!("expected true or false, got {raw:?}"))?),
+        Some(_) => return Err(format!("{} can't be set from the environment", path.join("."))),
+        None => raw.parse().map(Value::Integer)
+            .or_else(|_| raw.parse().map(Value::Float))
+            .or_else(|_| raw.parse().map(Value::Boolean))
+            .unwrap_or_else(|_| Value::String(raw.to_string())),
+    };
+    table.insert(key.clone(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(var, value)| (var.to_string(), value.to_string())).collect()
+    }
+
+    fn file(contents: &str) -> Option<Value> {
+        Some(Value::Table(toml::from_str(contents).unwrap()))
+    }
+
+    fn problems(result: Result<Config, ConfigError>) -> Vec<String> {
+        match result {
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("expected invalid settings, got {e}"),
+            Ok(_) => panic!("expected invalid settings, got a config"),
+        }
+    }
+
+    #[test]
+    fn env_overrides_nest_on_double_underscores() {
+        let config = build_config(None, vars(&[
+            ("NFMW_PORT", "9000"),
+            ("NFMW_TT__HISTORY_MAX_ENTRIES", "5"),
+            ("NFMW_TT__PLAUSIBILITY__MIN_TICKS_PER_CHECKPOINT", "50"),
+            ("OTHER_PORT", "1"),
+        ])).unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.tt.history_max_entries, 5);
+        assert_eq!(config.tt.plausibility.min_ticks_per_checkpoint, Some(50));
+    }
+
+    #[test]
+    fn env_overrides_the_file() {
+        let config = build_config(file("port = 9000\nfilestore = \"/srv\""), vars(&[("NFMW_PORT", "9001")])).unwrap();
+        assert_eq!(config.port, 9001);
+        assert_eq!(config.filestore, "/srv");
+    }
+
+    #[test]
+    fn env_values_must_match_the_setting_type() {
+        let result = build_config(None, vars(&[("NFMW_PORT", "eighty")]));
+        assert!(matches!(result, Err(ConfigError::Env(var, _)) if var == "NFMW_PORT"));
+    }
+
+    #[test]
+    fn file_suffix_reads_the_value_from_a_file() {
+        let path = env::temp_dir().join(format!("nfmw-config-test-{}", std::process::id()));
+        std::fs::write(&path, "hunter2\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = build_config(None, vars(&[("NFMW_DISCORD__CLIENT_SECRET_FILE", path)]));
+        let both = build_config(None, vars(&[
+            ("NFMW_DISCORD__CLIENT_SECRET", "x"),
+            ("NFMW_DISCORD__CLIENT_SECRET_FILE", path),
+        ]));
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(config.unwrap().discord.client_secret, "hunter2");
+        assert!(matches!(both, Err(ConfigError::Env(var, _)) if var == "NFMW_DISCORD__CLIENT_SECRET_FILE"));
+    }
+
+    #[test]
+    fn missing_value_file_is_an_error() {
+        let result = build_config(None, vars(&[("NFMW_DISCORD__CLIENT_SECRET_FILE", "/nonexistent/secret")]));
+        assert!(matches!(result, Err(ConfigError::Env(..))));
+    }
+
+    #[test]
+    fn off_unsets_optional_settings() {
+        assert_eq!(Config::default().tt.plausibility.max_record_improvement_percent, Some(10.0));
+
+        let config = build_config(file("[tt.plausibility]\nmax_record_improvement_percent = \"off\""), Vec::new()).unwrap();
+        assert_eq!(config.tt.plausibility.max_record_improvement_percent, None);
+
+        let config = build_config(
+            file("metrics_port = 9090"),
+            vars(&[("NFMW_METRICS_PORT", "off"), ("NFMW_TT__PLAUSIBILITY__MAX_RECORD_IMPROVEMENT_PERCENT", "off")]),
+        ).unwrap();
+        assert_eq!(config.metrics_port, None);
+        assert_eq!(config.tt.plausibility.max_record_improvement_percent, None);
+    }
+
+    #[test]
+    fn off_is_rejected_for_required_settings() {
+        assert_eq!(problems(build_config(None, vars(&[("NFMW_PORT", "off")]))).len(), 1);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert_eq!(problems(build_config(file("[tt]\nhistroy_max_entries = 3"), Vec::new())).len(), 1);
+        assert_eq!(problems(build_config(None, vars(&[("NFMW_TT__BOGUS", "1")]))).len(), 1);
+        let result = build_config(None, vars(&[("NFMW_BOGUS__SETTING", "1")]));
+        assert!(matches!(result, Err(ConfigError::Env(var, _)) if var == "NFMW_BOGUS__SETTING"));
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let config = Config {
+            port: 0,
+            filestore: " ".to_string(),
+            database: DatabaseConfig { max_connections: 0, acquire_timeout_secs: 0, ..DatabaseConfig::default() },
+            ..Config::default()
+        };
+        let Err(ConfigError::Invalid(problems)) = config.validate() else {
+            panic!("expected invalid settings");
+        };
+        assert_eq!(problems, [
+            "port must not be 0",
+            "filestore must not be empty",
+            "database.max_connections must be at least 1",
+            "database.acquire_timeout_secs must be at least 1",
+        ]);
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(build_config(None, Vec::new()).is_ok());
+    }
+}
@@ -1,15 +1,21 @@
-use sqlx::{PgPool, types::{Uuid, time::PrimitiveDateTime}};
+use std::collections::HashMap;
+
+use sqlx::{PgConnection, PgPool, types::{Uuid, time::PrimitiveDateTime}};
+
+use crate::archive::ArchiveItemKey;
 
 #[derive(sqlx::FromRow, Debug)]
 pub struct ArchiveItem {
-    // primary key
-    pub archive_item_id: Uuid,
+    // primary key is (author, name)
+    pub author: String,
+    pub name: String,
     pub path: String,
     pub r#type: String,
-    pub name: String,
     pub created_at: Option<PrimitiveDateTime>,
-    pub author: Option<String>,
-    pub owner_user_id: i32
+    pub owner_user_id: Option<i32>,
+    /// The UUID the item had before items were keyed by (author, name). Only set for items that existed then
+    /// or that are still stored under a UUID filename.
+    pub legacy_id: Option<Uuid>
 }
 impl ArchiveItem {
     pub async fn search_name(
@@ -20,18 +26,130 @@ impl ArchiveItem {
         todo!()
     }
 
+    pub async fn get_by_owner(pool: &PgPool, owner_user_id: i32) -> Result<Vec<Self>, sqlx::Error> {
+        let items = sqlx::query_as!(
+            Self,
+            r#"
+            SELECT author, name, path, type, created_at, owner_user_id, legacy_id
+            FROM archive_items
+            WHERE owner_user_id = $1
+            "#,
+            owner_user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(items)
+    }
+
+    pub async fn get_by_key(pool: &PgPool, key: &ArchiveItemKey) -> Result<Option<Self>, sqlx::Error> {
+        let item = sqlx::query_as!(
+            Self,
+            r#"
+            SELECT author, name, path, type, created_at, owner_user_id, legacy_id
+            FROM archive_items
+            WHERE author = $1 AND name = $2
+            "#,
+            key.author,
+            key.name
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(item)
+    }
+
+    /// Resolves a pre-(author, name) UUID to the item's current key.
+    pub async fn get_key_by_legacy_id(pool: &PgPool, legacy_id: Uuid) -> Result<Option<ArchiveItemKey>, sqlx::Error> {
+        let key = sqlx::query_as!(
+            ArchiveItemKey,
+            r#"
+            SELECT author, name
+            FROM archive_items
+            WHERE legacy_id = $1
+            "#,
+            legacy_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    /// Looks up the legacy UUIDs of several items at once, for responses that still report them to older clients.
+    /// Items without a legacy UUID are left out of the map.
+    pub async fn get_legacy_ids(pool: &PgPool, keys: &[ArchiveItemKey]) -> Result<HashMap<ArchiveItemKey, Uuid>, sqlx::Error> {
+        let authors = keys.iter().map(|k| k.author.clone()).collect::<Vec<_>>();
+        let names = keys.iter().map(|k| k.name.clone()).collect::<Vec<_>>();
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT author, name, legacy_id AS "legacy_id!"
+            FROM archive_items
+            WHERE (author, name) IN (SELECT * FROM UNNEST($1::text[], $2::text[]))
+                AND legacy_id IS NOT NULL
+            "#,
+            &authors,
+            &names
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|r| (ArchiveItemKey { author: r.author, name: r.name }, r.legacy_id))
+            .collect())
+    }
+
+    /// Number of items of each type, keyed by the stored type name. Types with no items are left out.
+    pub async fn count_by_type(pool: &PgPool) -> Result<HashMap<String, i64>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT type, COUNT(*) AS "count!"
+            FROM archive_items
+            GROUP BY type
+            "#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|r| (r.r#type, r.count)).collect())
+    }
+
+    /// Moves every item owned by `from_user_id` to `to_user_id`. Returns the number of items moved.
+    /// Takes a connection so that it can run in the same transaction as the deletion of the old owner.
+    pub async fn transfer_ownership(
+        conn: &mut PgConnection,
+        from_user_id: i32,
+        to_user_id: i32,
+    ) -> Result<u64, sqlx::Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE archive_items
+            SET owner_user_id = $1
+            WHERE owner_user_id = $2
+            "#,
+            to_user_id,
+            from_user_id
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(res.rows_affected())
+    }
+
     pub async fn insert(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
-            INSERT INTO archive_items (archive_item_id, path, type, name, author, owner_user_id)
+            INSERT INTO archive_items (author, name, path, type, owner_user_id, legacy_id)
             VALUES ($1, $2, $3, $4, $5, $6)
             "#,
-            self.archive_item_id,
+            self.author,
+            self.name,
             self.path,
             self.r#type,
-            self.name,
-            self.author,
-            self.owner_user_id
+            self.owner_user_id,
+            self.legacy_id
         )
         .execute(pool)
         .await?;
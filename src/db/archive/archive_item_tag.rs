@@ -1,18 +1,20 @@
-use sqlx::{PgPool, types::Uuid};
+use sqlx::PgPool;
 
 #[derive(sqlx::FromRow, Debug)]
 pub struct ArchiveItemTag {
-    pub archive_item_id: Uuid,
+    pub item_author: String,
+    pub item_name: String,
     pub tag_id: i32,
 }
 impl ArchiveItemTag {
     pub async fn insert(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
-            INSERT INTO archive_item_tags (archive_item_id, tag_id)
-            VALUES ($1, $2)
+            INSERT INTO archive_item_tags (item_author, item_name, tag_id)
+            VALUES ($1, $2, $3)
             "#,
-            self.archive_item_id,
+            self.item_author,
+            self.item_name,
             self.tag_id,
         )
         .execute(pool)
@@ -25,9 +27,10 @@ impl ArchiveItemTag {
         sqlx::query!(
             r#"
             DELETE FROM archive_item_tags
-            WHERE archive_item_id = $1 AND tag_id = $2
+            WHERE item_author = $1 AND item_name = $2 AND tag_id = $3
             "#,
-            self.archive_item_id,
+            self.item_author,
+            self.item_name,
             self.tag_id
         )
         .execute(pool)
@@ -0,0 +1,38 @@
+use sqlx::{PgConnection, migrate::{Migrate, Migrator}};
+
+use crate::config::MigrationMode;
+
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Brings the schema up to date, or with [`MigrationMode::Check`] only makes sure it already is. Takes a connection
+/// of its own rather than the pool, so that the pool's statement timeout doesn't cut a long migration short.
+pub async fn run_migrations(conn: &mut PgConnection, mode: MigrationMode) -> Result<(), String> {
+    match mode {
+        MigrationMode::Apply => MIGRATOR.run(conn).await.map_err(|e| e.to_string()),
+        MigrationMode::Check => {
+            let pending = get_pending(conn).await?;
+            if pending.is_empty() {
+                Ok(())
+            } else {
+                Err(format!("{} migrations are pending: {}", pending.len(), pending.join(", ")))
+            }
+        }
+    }
+}
+
+/// Versions and descriptions of the migrations that haven't been applied yet.
+async fn get_pending(conn: &mut PgConnection) -> Result<Vec<String>, String> {
+    conn.ensure_migrations_table().await.map_err(|e| e.to_string())?;
+
+    if let Some(version) = conn.dirty_version().await.map_err(|e| e.to_string())? {
+        return Err(format!("migration {version} was only partially applied"));
+    }
+    let applied = conn.list_applied_migrations().await.map_err(|e| e.to_string())?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| m.migration_type.is_up_migration())
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| format!("{} ({})", m.version, m.description))
+        .collect())
+}
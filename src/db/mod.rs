@@ -1,7 +1,11 @@
 use axum::{http::StatusCode, response::IntoResponse};
 
+pub mod migrate;
 pub mod user;
 pub mod token;
+pub mod user_role;
+pub mod user_settings;
+pub mod user_follow;
 pub mod archive;
 pub mod oauth2;
 pub mod tt;
\ No newline at end of file
@@ -22,6 +22,20 @@ impl DiscordOauth2AccountEntry {
         Ok(entry)
     }
 
+    pub async fn lookup_user_id(pool: &PgPool, user_id: i32) -> Result<Option<Self>, sqlx::Error> {
+        let entry = sqlx::query_as!(Self, r#"
+            SELECT * FROM discord_oauth2
+            WHERE user_id = $1
+            LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(entry)
+    }
+
     pub async fn create_oauth2_link(pool: &PgPool, user_id: i32, discord_user_id: i64) -> Result<(), sqlx::Error> {
         sqlx::query!(r#"
             INSERT INTO discord_oauth2 (user_id, discord_user_id)
@@ -37,6 +37,20 @@ impl UserToken {
         Ok(entry)
     }
 
+    pub async fn get_by_user_id(pool: &PgPool, user_id: i32) -> Result<Option<UserToken>, sqlx::Error> {
+        let entry = sqlx::query_as!(Self, r#"
+            SELECT * FROM user_tokens
+            WHERE user_id = $1
+            LIMIT 1
+            "#,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(entry)
+    }
+
     pub async fn remove_all(user_id: i32, pool: &sqlx::PgPool) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
@@ -0,0 +1,291 @@
+use sqlx::types::{Uuid, time::PrimitiveDateTime};
+
+use crate::{archive::ArchiveItemKey, db::tt::tt_history::TimeTrialHistoryEntry};
+
+// A time trial event on one stage, open to a set of cars for a time window. Runs uploaded inside the window are
+// entered automatically (see `record_run`); once the window closes, the standings are frozen into tt_event_results.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct TimeTrialEvent {
+    pub id: i32,
+    pub name: String,
+    pub stage_author: String,
+    pub stage_name: String,
+    pub starts_at: PrimitiveDateTime,
+    pub ends_at: PrimitiveDateTime,
+    pub created_at: PrimitiveDateTime,
+    pub frozen_at: Option<PrimitiveDateTime>,
+}
+
+/// A user's position in an event: their best run inside the window, or their final result once frozen.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct EventStanding {
+    pub rank: i64,
+    pub username: String,
+    pub car_author: String,
+    pub car_name: String,
+    /// History entry of the run, whose ghost is kept for the event. None if the ghost was deleted since.
+    pub history_id: Option<Uuid>,
+    pub tt_version: i32,
+    pub total_ticks: i32,
+    pub set_at: PrimitiveDateTime,
+}
+
+impl TimeTrialEvent {
+    pub fn stage(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.stage_author.clone(), name: self.stage_name.clone() }
+    }
+
+    pub async fn insert(
+        pool: &sqlx::PgPool,
+        name: &str,
+        stage: &ArchiveItemKey,
+        cars: &[ArchiveItemKey],
+        starts_at: PrimitiveDateTime,
+        ends_at: PrimitiveDateTime,
+        created_by: i32,
+    ) -> Result<Self, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let event = sqlx::query_as!(
+            TimeTrialEvent,
+            r#"
+            INSERT INTO tt_events (name, stage_author, stage_name, starts_at, ends_at, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, name, stage_author, stage_name, starts_at, ends_at, created_at, frozen_at
+            "#,
+            name,
+            stage.author,
+            stage.name,
+            starts_at,
+            ends_at,
+            created_by
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let (authors, names): (Vec<String>, Vec<String>) = cars.iter().map(|c| (c.author.clone(), c.name.clone())).unzip();
+        sqlx::query!(
+            r#"
+            INSERT INTO tt_event_cars (event_id, car_author, car_name)
+            SELECT $1, car_author, car_name
+            FROM UNNEST($2::text[], $3::text[]) AS c(car_author, car_name)
+            ON CONFLICT DO NOTHING
+            "#,
+            event.id,
+            &authors,
+            &names
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(event)
+    }
+
+    pub async fn get(pool: &sqlx::PgPool, event_id: i32) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TimeTrialEvent,
+            r#"
+            SELECT id, name, stage_author, stage_name, starts_at, ends_at, created_at, frozen_at
+            FROM tt_events
+            WHERE id = $1
+            "#,
+            event_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Events on a stage, or all events, most recent first.
+    pub async fn list(pool: &sqlx::PgPool, stage: Option<&ArchiveItemKey>) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TimeTrialEvent,
+            r#"
+            SELECT id, name, stage_author, stage_name, starts_at, ends_at, created_at, frozen_at
+            FROM tt_events
+            WHERE $1::text IS NULL OR (stage_author = $1 AND stage_name = $2)
+            ORDER BY starts_at DESC
+            "#,
+            stage.map(|s| s.author.as_str()),
+            stage.map(|s| s.name.as_str())
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn get_cars(pool: &sqlx::PgPool, event_id: i32) -> Result<Vec<ArchiveItemKey>, sqlx::Error> {
+        sqlx::query_as!(
+            ArchiveItemKey,
+            r#"
+            SELECT car_author AS author, car_name AS name
+            FROM tt_event_cars
+            WHERE event_id = $1
+            ORDER BY car_author, car_name
+            "#,
+            event_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Enters a run into every unfrozen event for its stage and car whose window contains the time the run was set,
+    /// where it beats the user's previous entry. Returns the IDs of the events it was entered into.
+    pub async fn record_run(
+        executor: impl sqlx::PgExecutor<'_>,
+        user_id: i32,
+        car: &ArchiveItemKey,
+        stage: &ArchiveItemKey,
+        run: &TimeTrialHistoryEntry,
+    ) -> Result<Vec<i32>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            INSERT INTO tt_event_entries (event_id, user_id, car_author, car_name, history_id, tt_version, total_ticks, set_at)
+            SELECT e.id, $1, $2, $3, $6, $7, $8, $9
+            FROM tt_events e
+            JOIN tt_event_cars c ON c.event_id = e.id AND c.car_author = $2 AND c.car_name = $3
+            WHERE e.stage_author = $4 AND e.stage_name = $5
+                AND e.starts_at <= $9 AND e.ends_at > $9 AND e.frozen_at IS NULL
+            ON CONFLICT (event_id, user_id) DO UPDATE
+            SET car_author = EXCLUDED.car_author, car_name = EXCLUDED.car_name, history_id = EXCLUDED.history_id,
+                tt_version = EXCLUDED.tt_version, total_ticks = EXCLUDED.total_ticks, set_at = EXCLUDED.set_at
+            WHERE EXCLUDED.total_ticks < tt_event_entries.total_ticks
+            RETURNING event_id
+            "#,
+            user_id,
+            car.author,
+            car.name,
+            stage.author,
+            stage.name,
+            run.id,
+            run.tt_version,
+            run.total_ticks,
+            run.created_at
+        )
+        .fetch_all(executor)
+        .await
+    }
+
+    /// Whether the best run of a time trial is an entry in an event that hasn't ended yet. Such ghosts are hidden
+    /// from other players until the event ends, so nobody can copy a rival's line mid-event.
+    pub async fn holds_ghost(pool: &sqlx::PgPool, tt_id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM tt_event_entries en
+                JOIN tt_events e ON e.id = en.event_id
+                JOIN time_trial_history h ON h.id = en.history_id
+                WHERE h.time_trial_id = $1 AND h.is_best AND e.ends_at > NOW()
+            ) AS "exists!"
+            "#,
+            tt_id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    /// As `holds_ghost`, for one specific run from a time trial's history.
+    pub async fn holds_history_ghost(pool: &sqlx::PgPool, history_id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM tt_event_entries en
+                JOIN tt_events e ON e.id = en.event_id
+                WHERE en.history_id = $1 AND e.ends_at > NOW()
+            ) AS "exists!"
+            "#,
+            history_id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Freezes the results of every event whose window has closed. Returns the IDs of the events frozen.
+    pub async fn freeze_ended(pool: &sqlx::PgPool) -> Result<Vec<i32>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            WITH ended AS (
+                UPDATE tt_events
+                SET frozen_at = NOW()
+                WHERE frozen_at IS NULL AND ends_at <= NOW()
+                RETURNING id
+            ),
+            results AS (
+                INSERT INTO tt_event_results
+                    (event_id, rank, user_id, username, car_author, car_name, history_id, tt_version, total_ticks, set_at)
+                SELECT en.event_id,
+                    ROW_NUMBER() OVER (PARTITION BY en.event_id ORDER BY en.total_ticks ASC, en.set_at ASC)::int,
+                    en.user_id, u.username, en.car_author, en.car_name, en.history_id, en.tt_version, en.total_ticks,
+                    en.set_at
+                FROM tt_event_entries en
+                JOIN ended ON ended.id = en.event_id
+                JOIN users u ON u.id = en.user_id
+            )
+            SELECT id FROM ended
+            "#
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// One page of the event's standings: the frozen results if the event is over, the current entries otherwise.
+    /// Also returns the total number of entries.
+    pub async fn standings(&self, pool: &sqlx::PgPool, limit: i64, offset: i64) -> Result<(Vec<EventStanding>, i64), sqlx::Error> {
+        if self.frozen_at.is_some() {
+            let entries = sqlx::query_as!(
+                EventStanding,
+                r#"
+                SELECT rank::bigint AS "rank!", username, car_author, car_name, history_id, tt_version, total_ticks, set_at
+                FROM tt_event_results
+                WHERE event_id = $1
+                ORDER BY rank ASC
+                LIMIT $2 OFFSET $3
+                "#,
+                self.id,
+                limit,
+                offset
+            )
+            .fetch_all(pool)
+            .await?;
+            let total = sqlx::query_scalar!(
+                r#"SELECT COUNT(*) AS "count!" FROM tt_event_results WHERE event_id = $1"#,
+                self.id
+            )
+            .fetch_one(pool)
+            .await?;
+            return Ok((entries, total));
+        }
+
+        let entries = sqlx::query_as!(
+            EventStanding,
+            r#"
+            SELECT ROW_NUMBER() OVER (ORDER BY en.total_ticks ASC, en.set_at ASC) AS "rank!", u.username,
+                en.car_author, en.car_name, en.history_id, en.tt_version, en.total_ticks, en.set_at
+            FROM tt_event_entries en
+            JOIN users u ON u.id = en.user_id
+            WHERE en.event_id = $1
+            ORDER BY en.total_ticks ASC, en.set_at ASC
+            LIMIT $2 OFFSET $3
+            "#,
+            self.id,
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+        .await?;
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM tt_event_entries WHERE event_id = $1"#,
+            self.id
+        )
+        .fetch_one(pool)
+        .await?;
+        Ok((entries, total))
+    }
+}
+
+impl EventStanding {
+    pub fn car(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.car_author.clone(), name: self.car_name.clone() }
+    }
+}
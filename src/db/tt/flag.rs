@@ -0,0 +1,155 @@
+use sqlx::types::{Uuid, time::PrimitiveDateTime};
+
+use crate::archive::ArchiveItemKey;
+
+// A run that simulated correctly but failed a plausibility check (see `tt::plausibility`). Flags stay pending until
+// a moderator approves or rejects the run. Until then the run is only kept in the history, even if it beats the
+// user's best; the flag holds what's needed to make it the best once approved. The exception is a user's first run on
+// a car and stage, which creates their time trial: that time trial is kept off leaderboards while the flag is pending
+// (`time_trials.flagged_at`).
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct TimeTrialFlag {
+    pub id: i32,
+    pub time_trial_id: Uuid,
+    /// None if the run's history entry was deleted since
+    pub history_id: Option<Uuid>,
+    pub reviewed_at: Option<PrimitiveDateTime>,
+    /// Backend version the run was validated with; None for flags recorded before it was kept
+    pub backend_version: Option<i32>,
+    /// Splits of the run, as for `TimeTrialEntry::set_splits`; None for flags recorded before they were kept
+    pub checkpoint_ticks: Option<Vec<i32>>,
+}
+
+/// A pending flag with what a moderator needs to review it.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct PendingFlag {
+    pub id: i32,
+    pub time_trial_id: Uuid,
+    pub history_id: Option<Uuid>,
+    pub username: String,
+    pub car_author: String,
+    pub car_name: String,
+    pub stage_author: String,
+    pub stage_name: String,
+    pub total_ticks: i32,
+    pub reasons: Vec<String>,
+    pub flagged_at: PrimitiveDateTime,
+    /// Whether the flagged run is the user's best for the car and stage, i.e. it was their first run on them
+    pub is_best: bool,
+    /// Whether approving the run would make it the user's best
+    pub improves: bool,
+}
+
+impl TimeTrialFlag {
+    pub async fn insert(
+        executor: impl sqlx::PgExecutor<'_>,
+        time_trial_id: Uuid,
+        history_id: Uuid,
+        total_ticks: i32,
+        reasons: &[String],
+        backend_version: i32,
+        checkpoint_ticks: &[i32],
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query_as!(
+            TimeTrialFlag,
+            r#"
+            INSERT INTO tt_flags (time_trial_id, history_id, total_ticks, reasons, backend_version, checkpoint_ticks)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, time_trial_id, history_id, reviewed_at, backend_version, checkpoint_ticks
+            "#,
+            time_trial_id,
+            history_id,
+            total_ticks,
+            reasons,
+            backend_version,
+            checkpoint_ticks
+        )
+        .fetch_one(executor)
+        .await
+    }
+
+    pub async fn get(pool: &sqlx::PgPool, flag_id: i32) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TimeTrialFlag,
+            r#"
+            SELECT id, time_trial_id, history_id, reviewed_at, backend_version, checkpoint_ticks
+            FROM tt_flags
+            WHERE id = $1
+            "#,
+            flag_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// The moderation queue, oldest first, with the total number of pending flags.
+    pub async fn get_pending(pool: &sqlx::PgPool, limit: i64, offset: i64) -> Result<(Vec<PendingFlag>, i64), sqlx::Error> {
+        let flags = sqlx::query_as!(
+            PendingFlag,
+            r#"
+            SELECT f.id, f.time_trial_id, f.history_id, u.username, tt.car_author, tt.car_name, tt.stage_author,
+                tt.stage_name, f.total_ticks, f.reasons, f.flagged_at, COALESCE(h.is_best, FALSE) AS "is_best!",
+                COALESCE(NOT h.is_best AND (tt.invalidated_at IS NOT NULL OR f.total_ticks < tt.total_ticks), FALSE)
+                    AS "improves!"
+            FROM tt_flags f
+            JOIN time_trials tt ON tt.id = f.time_trial_id
+            JOIN users u ON u.id = tt.user_id
+            LEFT JOIN time_trial_history h ON h.id = f.history_id
+            WHERE f.reviewed_at IS NULL
+            ORDER BY f.flagged_at ASC, f.id ASC
+            LIMIT $1 OFFSET $2
+            "#,
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM tt_flags
+            WHERE reviewed_at IS NULL
+            "#
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok((flags, total))
+    }
+
+    /// Records a moderator's verdict. Returns false if the flag had already been reviewed.
+    pub async fn review(
+        executor: impl sqlx::PgExecutor<'_>,
+        flag_id: i32,
+        reviewer_id: i32,
+        approved: bool,
+        note: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
+            r#"
+            UPDATE tt_flags
+            SET reviewed_at = NOW(), reviewed_by = $1, approved = $2, review_note = $3
+            WHERE id = $4 AND reviewed_at IS NULL
+            "#,
+            reviewer_id,
+            approved,
+            note,
+            flag_id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(res.rows_affected() == 1)
+    }
+}
+
+impl PendingFlag {
+    pub fn car(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.car_author.clone(), name: self.car_name.clone() }
+    }
+
+    pub fn stage(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.stage_author.clone(), name: self.stage_name.clone() }
+    }
+}
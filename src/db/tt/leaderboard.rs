@@ -0,0 +1,192 @@
+use sqlx::types::{Uuid, time::PrimitiveDateTime};
+
+use crate::archive::ArchiveItemKey;
+
+/// A time trial with its position on a leaderboard. Ranks start at 1 and are unique within a board;
+/// equal tick counts are ranked by whoever set the time first, and exact ties by ID.
+///
+/// Each user appears at most once per board. On a single car board that is guaranteed by the
+/// (user, car, stage) key; on a stage-wide board each user is represented by their fastest car.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub rank: i64,
+    pub id: Uuid,
+    pub user_id: i32,
+    pub username: String,
+    pub car_author: String,
+    pub car_name: String,
+    pub stage_author: String,
+    pub stage_name: String,
+    pub tt_version: i32,
+    pub total_ticks: i32,
+    pub created_at: PrimitiveDateTime,
+}
+
+pub struct LeaderboardPage {
+    pub entries: Vec<LeaderboardEntry>,
+    /// Number of ranked entries on the whole board, not just this page
+    pub total: i64,
+}
+
+/// The fastest time trial set with one car on a stage.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct CarRecord {
+    pub car_author: String,
+    pub car_name: String,
+    /// Number of ranked time trials on this stage with this car
+    pub entry_count: i64,
+    pub id: Uuid,
+    pub username: String,
+    pub tt_version: i32,
+    pub total_ticks: i32,
+    pub created_at: PrimitiveDateTime,
+}
+
+impl LeaderboardEntry {
+    pub fn car(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.car_author.clone(), name: self.car_name.clone() }
+    }
+
+    pub fn stage(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.stage_author.clone(), name: self.stage_name.clone() }
+    }
+
+    /// Fetches one page of the leaderboard for a stage, optionally restricted to one car.
+    /// Entries with a `tt_version` below `min_version`, and invalidated entries, are not ranked.
+    pub async fn page(
+        pool: &sqlx::PgPool,
+        stage: &ArchiveItemKey,
+        car: Option<&ArchiveItemKey>,
+        min_version: i32,
+        limit: i64,
+        offset: i64,
+    ) -> Result<LeaderboardPage, sqlx::Error> {
+        let entries = Self::ranked(pool, stage, car, min_version, None, limit, offset).await?;
+
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(DISTINCT user_id) AS "count!"
+            FROM time_trials
+            WHERE stage_author = $1 AND stage_name = $2
+                AND ($3::text IS NULL OR (car_author = $3 AND car_name = $4))
+                AND tt_version >= $5
+                AND invalidated_at IS NULL
+                AND flagged_at IS NULL
+            "#,
+            stage.author,
+            stage.name,
+            car.map(|c| c.author.as_str()),
+            car.map(|c| c.name.as_str()),
+            min_version
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(LeaderboardPage { entries, total })
+    }
+
+    /// The entry of a single user on the same board as `page`, regardless of which page it falls on.
+    pub async fn user_entry(
+        pool: &sqlx::PgPool,
+        stage: &ArchiveItemKey,
+        car: Option<&ArchiveItemKey>,
+        min_version: i32,
+        user_id: i32,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        Ok(Self::ranked(pool, stage, car, min_version, Some(user_id), 1, 0).await?.pop())
+    }
+
+    /// The ranked board behind `page` and `user_entry`, filtered to one user if `user_id` is set. Ranks are
+    /// assigned before that filter, so they're the same either way.
+    async fn ranked(
+        pool: &sqlx::PgPool,
+        stage: &ArchiveItemKey,
+        car: Option<&ArchiveItemKey>,
+        min_version: i32,
+        user_id: Option<i32>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            LeaderboardEntry,
+            r#"
+            WITH best AS (
+                SELECT DISTINCT ON (tt.user_id) tt.id, tt.user_id, tt.car_author, tt.car_name, tt.stage_author, tt.stage_name,
+                    tt.tt_version, tt.total_ticks, tt.created_at
+                FROM time_trials tt
+                WHERE tt.stage_author = $1 AND tt.stage_name = $2
+                    AND ($3::text IS NULL OR (tt.car_author = $3 AND tt.car_name = $4))
+                    AND tt.tt_version >= $5
+                    AND tt.invalidated_at IS NULL
+                    AND tt.flagged_at IS NULL
+                ORDER BY tt.user_id, tt.total_ticks ASC, tt.created_at ASC, tt.id ASC
+            ),
+            ranked AS (
+                SELECT ROW_NUMBER() OVER (ORDER BY best.total_ticks ASC, best.created_at ASC, best.id ASC) AS rank, best.*
+                FROM best
+            )
+            SELECT ranked.rank AS "rank!", ranked.id AS "id!", ranked.user_id AS "user_id!", u.username,
+                ranked.car_author AS "car_author!", ranked.car_name AS "car_name!",
+                ranked.stage_author AS "stage_author!", ranked.stage_name AS "stage_name!", ranked.tt_version AS "tt_version!",
+                ranked.total_ticks AS "total_ticks!", ranked.created_at AS "created_at!"
+            FROM ranked
+            JOIN users u ON u.id = ranked.user_id
+            WHERE $6::int IS NULL OR ranked.user_id = $6
+            ORDER BY ranked.rank ASC
+            LIMIT $7 OFFSET $8
+            "#,
+            stage.author,
+            stage.name,
+            car.map(|c| c.author.as_str()),
+            car.map(|c| c.name.as_str()),
+            min_version,
+            user_id,
+            limit,
+            offset
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+impl CarRecord {
+    pub fn car(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.car_author.clone(), name: self.car_name.clone() }
+    }
+
+    /// The record for every car that has at least one ranked time trial on the stage, fastest first.
+    pub async fn for_stage(
+        pool: &sqlx::PgPool,
+        stage: &ArchiveItemKey,
+        min_version: i32,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            CarRecord,
+            r#"
+            SELECT car_author AS "car_author!", car_name AS "car_name!", entry_count AS "entry_count!", id AS "id!",
+                username AS "username!", tt_version AS "tt_version!", total_ticks AS "total_ticks!",
+                created_at AS "created_at!"
+            FROM (
+                SELECT DISTINCT ON (tt.car_author, tt.car_name)
+                    tt.car_author, tt.car_name, COUNT(*) OVER (PARTITION BY tt.car_author, tt.car_name) AS entry_count,
+                    tt.id, u.username, tt.tt_version, tt.total_ticks, tt.created_at
+                FROM time_trials tt
+                JOIN users u ON u.id = tt.user_id
+                WHERE tt.stage_author = $1 AND tt.stage_name = $2
+                    AND tt.tt_version >= $3
+                    AND tt.invalidated_at IS NULL
+                    AND tt.flagged_at IS NULL
+                ORDER BY tt.car_author, tt.car_name, tt.total_ticks ASC, tt.created_at ASC, tt.id ASC
+            ) records
+            ORDER BY total_ticks ASC, created_at ASC, id ASC
+            "#,
+            stage.author,
+            stage.name,
+            min_version
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(records)
+    }
+}
\ No newline at end of file
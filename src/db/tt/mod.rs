@@ -1 +1,6 @@
-pub mod tt_entry;
\ No newline at end of file
+pub mod event;
+pub mod flag;
+pub mod leaderboard;
+pub mod revalidation;
+pub mod tt_entry;
+pub mod tt_history;
\ No newline at end of file
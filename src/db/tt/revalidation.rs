@@ -0,0 +1,117 @@
+use sqlx::types::{Uuid, time::PrimitiveDateTime};
+
+use crate::archive::ArchiveItemKey;
+
+/// One pass of re-simulating stored time trials after the simulation backend changed version.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct RevalidationRun {
+    pub id: i32,
+    pub backend_version: i32,
+    pub started_at: PrimitiveDateTime,
+    /// None while the run is in progress, or if it was interrupted
+    pub finished_at: Option<PrimitiveDateTime>,
+    pub checked: i32,
+    pub invalidated: i32,
+}
+
+/// A time trial that a revalidation run marked invalid.
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct InvalidatedTimeTrial {
+    pub id: Uuid,
+    pub username: String,
+    pub car_author: String,
+    pub car_name: String,
+    pub stage_author: String,
+    pub stage_name: String,
+    pub total_ticks: i32,
+    pub invalid_reason: Option<String>,
+}
+
+impl RevalidationRun {
+    pub async fn start(pool: &sqlx::PgPool, backend_version: i32) -> Result<Self, sqlx::Error> {
+        sqlx::query_as!(
+            RevalidationRun,
+            r#"
+            INSERT INTO tt_revalidation_runs (backend_version)
+            VALUES ($1)
+            RETURNING id, backend_version, started_at, finished_at, checked, invalidated
+            "#,
+            backend_version
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    pub async fn finish(pool: &sqlx::PgPool, run_id: i32, checked: i32, invalidated: i32) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE tt_revalidation_runs
+            SET finished_at = NOW(), checked = $1, invalidated = $2
+            WHERE id = $3
+            "#,
+            checked,
+            invalidated,
+            run_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &sqlx::PgPool, run_id: i32) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            RevalidationRun,
+            r#"
+            SELECT id, backend_version, started_at, finished_at, checked, invalidated
+            FROM tt_revalidation_runs
+            WHERE id = $1
+            "#,
+            run_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    pub async fn get_latest(pool: &sqlx::PgPool) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            RevalidationRun,
+            r#"
+            SELECT id, backend_version, started_at, finished_at, checked, invalidated
+            FROM tt_revalidation_runs
+            ORDER BY id DESC
+            LIMIT 1
+            "#
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// The time trials this run invalidated that haven't since been replaced by a new upload.
+    pub async fn get_invalidated(&self, pool: &sqlx::PgPool) -> Result<Vec<InvalidatedTimeTrial>, sqlx::Error> {
+        sqlx::query_as!(
+            InvalidatedTimeTrial,
+            r#"
+            SELECT tt.id, u.username, tt.car_author, tt.car_name, tt.stage_author, tt.stage_name,
+                tt.total_ticks, tt.invalid_reason
+            FROM time_trials tt
+            JOIN users u ON u.id = tt.user_id
+            WHERE tt.invalidated_by_run = $1
+            ORDER BY u.username, tt.stage_author, tt.stage_name
+            "#,
+            self.id
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+impl InvalidatedTimeTrial {
+    pub fn car(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.car_author.clone(), name: self.car_name.clone() }
+    }
+
+    pub fn stage(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.stage_author.clone(), name: self.stage_name.clone() }
+    }
+}
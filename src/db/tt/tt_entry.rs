@@ -1,101 +1,202 @@
-use sqlx::types::{Uuid, time::PrimitiveDateTime};
+use sqlx::{Postgres, QueryBuilder, types::{Uuid, time::PrimitiveDateTime}};
+
+use crate::archive::ArchiveItemKey;
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeTrialSort {
+    /// Fewest ticks first; ties go to whoever set the time first
+    #[default]
+    Fastest,
+    Newest,
+}
+
+/// Filters for `TimeTrialEntry::search`. Unset filters match everything.
+pub struct TimeTrialQuery<'a> {
+    pub user_id: Option<i32>,
+    /// The user searching, if logged in. Invalidated runs and runs awaiting review are only listed for their owner.
+    pub viewer_id: Option<i32>,
+    pub car: Option<&'a ArchiveItemKey>,
+    pub stage: Option<&'a ArchiveItemKey>,
+    pub sort: TimeTrialSort,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct TimeTrialSearchResult {
+    #[sqlx(flatten)]
+    pub entry: TimeTrialEntry,
+    pub username: String,
+}
 
 // The time trial uuid is the same as the stored time trial data filename in the filestore.
 // I.e., "tt_data/{time_trial_entry_id}.timetrial"
+// Cars and stages are referenced by their (author, name) key, like everywhere else in the archive.
 // In general, time trials that are an old version will be retained, but the client will not load the ghost itself (only the splits and other metadata).
 #[derive(sqlx::FromRow, Debug, Clone)]
 pub struct TimeTrialEntry {
     pub id: Uuid,
     pub user_id: i32,
-    pub car_id: Uuid,
-    pub stage_id: Uuid,
+    pub car_author: String,
+    pub car_name: String,
+    pub stage_author: String,
+    pub stage_name: String,
     pub tt_version: i32,
     pub total_ticks: i32,
-    pub created_at: Option<PrimitiveDateTime>
+    pub created_at: Option<PrimitiveDateTime>,
+    /// Set when the run stopped reproducing after a backend update; see `tt::revalidate`.
+    pub invalidated_at: Option<PrimitiveDateTime>
 }
 impl TimeTrialEntry {
-    /// Inserts a new time trial entry or updates the created_at timestamp if one already exists for the same user, car, and stage.
-    /// The time trial ID is returned, which can be used as the filename to store the actual time trial data file.
-    pub async fn insert(pool: &sqlx::PgPool, user_id: i32, car_id: Uuid, stage_id: Uuid, tt_version: i32, total_ticks: i32) -> Result<Self, sqlx::Error> {
-        // The file is handled separately; this just creates or updates the DB entry.
-        // Since a conflict will return the same entry ID, this can be used by the file handler to replace the existing file.
+    pub fn car(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.car_author.clone(), name: self.car_name.clone() }
+    }
+
+    pub fn stage(&self) -> ArchiveItemKey {
+        ArchiveItemKey { author: self.stage_author.clone(), name: self.stage_name.clone() }
+    }
+
+    /// Inserts the time trial for a user's first run on a car and stage; later runs go through `update`. Returns None,
+    /// inserting nothing, if the user already has one, which may have been inserted by a concurrent upload.
+    /// The time trial ID is returned, which is also the name of its file in the filestore.
+    pub async fn insert(executor: impl sqlx::PgExecutor<'_>, user_id: i32, car: &ArchiveItemKey, stage: &ArchiveItemKey, tt_version: i32, backend_version: i32, total_ticks: i32) -> Result<Option<Self>, sqlx::Error> {
         let res = sqlx::query_as!(
             Self,
             r#"
-            INSERT INTO time_trials (user_id, car_id, stage_id, tt_version, total_ticks)
-            VALUES ($1, $2, $3, $4, $5)
-            ON CONFLICT (user_id, car_id, stage_id) DO UPDATE
-            SET created_at = NOW()
-            RETURNING id, user_id, car_id, stage_id, tt_version, total_ticks, created_at
+            INSERT INTO time_trials (id, user_id, car_author, car_name, stage_author, stage_name, tt_version, total_ticks, backend_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (user_id, car_author, car_name, stage_author, stage_name) DO NOTHING
+            RETURNING id, user_id, car_author, car_name, stage_author, stage_name, tt_version, total_ticks, created_at, invalidated_at
             "#,
+            Uuid::new_v4(),
             user_id,
-            car_id,
-            stage_id,
+            car.author,
+            car.name,
+            stage.author,
+            stage.name,
             tt_version,
-            total_ticks
+            total_ticks,
+            backend_version
         )
-        .fetch_one(pool)
+        .fetch_optional(executor)
         .await?;
 
         Ok(res)
     }
 
-    pub async fn update(pool: &sqlx::PgPool, tt_id: Uuid, tt_version: i32, total_ticks: i32) -> Result<(), sqlx::Error> {
-        sqlx::query!(
+    /// Replaces the run with a new best, which also clears any invalidation. Returns false, changing nothing, if the
+    /// time trial holds a valid run at least as fast. The check is part of the update, so that of two concurrent
+    /// runs only a faster one can replace the other.
+    pub async fn update(executor: impl sqlx::PgExecutor<'_>, tt_id: Uuid, tt_version: i32, backend_version: i32, total_ticks: i32) -> Result<bool, sqlx::Error> {
+        let res = sqlx::query!(
             r#"
             UPDATE time_trials
-            SET tt_version = $1, total_ticks = $2, created_at = NOW()
-            WHERE id = $3
+            SET tt_version = $1, backend_version = $2, total_ticks = $3, created_at = NOW(),
+                invalidated_at = NULL, invalid_reason = NULL, invalidated_by_run = NULL, flagged_at = NULL
+            WHERE id = $4 AND (total_ticks > $3 OR invalidated_at IS NOT NULL)
             "#,
             tt_version,
+            backend_version,
             total_ticks,
             tt_id
         )
-        .execute(pool)
+        .execute(executor)
         .await?;
 
-        Ok(())
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// The ID of a user's time trial on a car and stage, if they have one.
+    pub async fn get_id(executor: impl sqlx::PgExecutor<'_>, user_id: i32, car: &ArchiveItemKey, stage: &ArchiveItemKey) -> Result<Option<Uuid>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM time_trials
+            WHERE user_id = $1 AND car_author = $2 AND car_name = $3 AND stage_author = $4 AND stage_name = $5
+            "#,
+            user_id,
+            car.author,
+            car.name,
+            stage.author,
+            stage.name
+        )
+        .fetch_optional(executor)
+        .await
     }
 
+    /// All time trials matching every given filter, in no particular order. For user-facing searches use `search`.
+    /// With no filters at all nothing is returned, rather than the whole table.
     pub async fn filter(
         pool: &sqlx::PgPool,
         user_id: Option<i32>,
-        car_id: Option<Uuid>,
-        stage_id: Option<Uuid>,
+        car: Option<&ArchiveItemKey>,
+        stage: Option<&ArchiveItemKey>,
     ) -> Result<Vec<Self>, sqlx::Error> {
-        let mut filtered: Option<Vec<Self>> = None;
-
-        if let Some(uid) = user_id {
-           filtered = Some(Self::filter_by_user(pool, uid).await?);
+        if user_id.is_none() && car.is_none() && stage.is_none() {
+            return Ok(Vec::new());
         }
 
-        if let Some(cid) = car_id {
-            if let Some(ref f) = filtered {
-                filtered = Some(f
-                    .iter()
-                    .cloned()
-                    .filter(|i| i.car_id == cid)
-                    .collect());
-            } else {
-                let car_filtered = Self::filter_by_car(pool, cid).await?;
-                filtered = Some(car_filtered);
-            }
-        }
+        let mut qb = QueryBuilder::<Postgres>::new(
+            "SELECT tt.id, tt.user_id, tt.car_author, tt.car_name, tt.stage_author, tt.stage_name, tt.created_at, tt.tt_version, tt.total_ticks, \
+            tt.invalidated_at \
+            FROM time_trials tt WHERE TRUE"
+        );
+        Self::push_filters(&mut qb, user_id, car, stage);
 
-        if let Some(sid) = stage_id {
-            if let Some(ref f) = filtered {
-                filtered = Some(f
-                    .iter()
-                    .cloned()
-                    .filter(|i| i.stage_id == sid)
-                    .collect());
-            } else {
-                let stage_filtered = Self::filter_by_stage(pool, sid).await?;
-                filtered = Some(stage_filtered);
-            }
+        qb.build_query_as::<Self>()
+            .fetch_all(pool)
+            .await
+    }
+
+    /// Searches time trials with all filtering, sorting and paging done by Postgres. The usernames are joined in,
+    /// so no further lookups are needed to present the results.
+    pub async fn search(pool: &sqlx::PgPool, query: &TimeTrialQuery<'_>) -> Result<Vec<TimeTrialSearchResult>, sqlx::Error> {
+        let mut qb = QueryBuilder::<Postgres>::new(
+            "SELECT tt.id, tt.user_id, tt.car_author, tt.car_name, tt.stage_author, tt.stage_name, tt.created_at, tt.tt_version, tt.total_ticks, \
+            tt.invalidated_at, u.username \
+            FROM time_trials tt \
+            JOIN users u ON u.id = tt.user_id \
+            WHERE TRUE"
+        );
+        Self::push_filters(&mut qb, query.user_id, query.car, query.stage);
+        qb.push(" AND ((tt.invalidated_at IS NULL AND tt.flagged_at IS NULL)");
+        if let Some(viewer) = query.viewer_id {
+            qb.push(" OR tt.user_id = ").push_bind(viewer);
         }
+        qb.push(")");
 
-        Ok(filtered.unwrap_or_else(|| vec![]))
+        qb.push(match query.sort {
+            // The id breaks ties so that paging is stable
+            TimeTrialSort::Fastest => " ORDER BY tt.total_ticks ASC, tt.created_at ASC, tt.id",
+            TimeTrialSort::Newest => " ORDER BY tt.created_at DESC, tt.id",
+        });
+        qb.push(" LIMIT ").push_bind(query.limit);
+        qb.push(" OFFSET ").push_bind(query.offset);
+
+        qb.build_query_as::<TimeTrialSearchResult>()
+            .fetch_all(pool)
+            .await
+    }
+
+    // Column pairs are compared together so that the (author, name) indexes on time_trials can be used.
+    fn push_filters(
+        qb: &mut QueryBuilder<'_, Postgres>,
+        user_id: Option<i32>,
+        car: Option<&ArchiveItemKey>,
+        stage: Option<&ArchiveItemKey>,
+    ) {
+        if let Some(uid) = user_id {
+            qb.push(" AND tt.user_id = ").push_bind(uid);
+        }
+        if let Some(car) = car {
+            qb.push(" AND tt.car_author = ").push_bind(car.author.clone());
+            qb.push(" AND tt.car_name = ").push_bind(car.name.clone());
+        }
+        if let Some(stage) = stage {
+            qb.push(" AND tt.stage_author = ").push_bind(stage.author.clone());
+            qb.push(" AND tt.stage_name = ").push_bind(stage.name.clone());
+        }
     }
 
     pub async fn filter_by_user(
@@ -105,7 +206,7 @@ impl TimeTrialEntry {
         let tts = sqlx::query_as!(
             TimeTrialEntry,
             r#"
-            SELECT id, user_id, car_id, stage_id, created_at, tt_version, total_ticks
+            SELECT id, user_id, car_author, car_name, stage_author, stage_name, created_at, tt_version, total_ticks, invalidated_at
             FROM time_trials
             WHERE user_id = $1
             "#,
@@ -117,48 +218,240 @@ impl TimeTrialEntry {
         Ok(tts)
     }
 
-    pub async fn filter_by_car(
+    /// A user's time trials as shown to others: their best run on each car and stage, leaving out runs kept off
+    /// the leaderboards. `include_hidden` lists those as well, for the owner.
+    pub async fn personal_bests(
         pool: &sqlx::PgPool,
-        car_id: Uuid,
+        user_id: i32,
+        include_hidden: bool,
     ) -> Result<Vec<Self>, sqlx::Error> {
-        let tts = sqlx::query_as!(
+        sqlx::query_as!(
             TimeTrialEntry,
             r#"
-            SELECT id, user_id, car_id, stage_id, created_at, tt_version, total_ticks
+            SELECT id, user_id, car_author, car_name, stage_author, stage_name, created_at, tt_version, total_ticks, invalidated_at
             FROM time_trials
-            WHERE car_id = $1
+            WHERE user_id = $1 AND ($2 OR (invalidated_at IS NULL AND flagged_at IS NULL))
+            ORDER BY total_ticks ASC, created_at ASC, id
             "#,
-            car_id
+            user_id,
+            include_hidden
         )
         .fetch_all(pool)
+        .await
+    }
+
+    /// Stores the splits of the current best run. An empty slice clears them.
+    pub async fn set_splits(executor: impl sqlx::PgExecutor<'_>, tt_id: Uuid, checkpoint_ticks: &[i32]) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE time_trials
+            SET checkpoint_ticks = $1
+            WHERE id = $2
+            "#,
+            (!checkpoint_ticks.is_empty()).then_some(checkpoint_ticks),
+            tt_id
+        )
+        .execute(executor)
         .await?;
 
-        Ok(tts)
+        Ok(())
     }
 
-    pub async fn filter_by_stage(
-        pool: &sqlx::PgPool,
-        stage_id: Uuid,
-    ) -> Result<Vec<Self>, sqlx::Error> {
-        let tts = sqlx::query_as!(
+    pub async fn get(pool: &sqlx::PgPool, tt_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TimeTrialEntry,
+            r#"
+            SELECT id, user_id, car_author, car_name, stage_author, stage_name, created_at, tt_version, total_ticks, invalidated_at
+            FROM time_trials
+            WHERE id = $1
+            "#,
+            tt_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// The time trial a history entry belongs to.
+    pub async fn get_by_history_id(pool: &sqlx::PgPool, history_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TimeTrialEntry,
+            r#"
+            SELECT tt.id, tt.user_id, tt.car_author, tt.car_name, tt.stage_author, tt.stage_name, tt.created_at, tt.tt_version, tt.total_ticks, tt.invalidated_at
+            FROM time_trials tt
+            JOIN time_trial_history h ON h.time_trial_id = tt.id
+            WHERE h.id = $1
+            "#,
+            history_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// The most recently set runs that are still valid, newest first. Their ghosts are used to find out the current
+    /// backend version.
+    pub async fn get_latest_valid(pool: &sqlx::PgPool, limit: i64) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
             TimeTrialEntry,
             r#"
-            SELECT id, user_id, car_id, stage_id, created_at, tt_version, total_ticks
+            SELECT id, user_id, car_author, car_name, stage_author, stage_name, created_at, tt_version, total_ticks, invalidated_at
             FROM time_trials
-            WHERE stage_id = $1
+            WHERE invalidated_at IS NULL
+            ORDER BY created_at DESC
+            LIMIT $1
             "#,
-            stage_id
+            limit
         )
         .fetch_all(pool)
+        .await
+    }
+
+    /// Valid runs that weren't validated with `backend_version`.
+    pub async fn get_needing_revalidation(pool: &sqlx::PgPool, backend_version: i32) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TimeTrialEntry,
+            r#"
+            SELECT id, user_id, car_author, car_name, stage_author, stage_name, created_at, tt_version, total_ticks, invalidated_at
+            FROM time_trials
+            WHERE invalidated_at IS NULL AND backend_version IS DISTINCT FROM $1
+            ORDER BY created_at ASC
+            "#,
+            backend_version
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    pub async fn set_backend_version(executor: impl sqlx::PgExecutor<'_>, tt_id: Uuid, backend_version: i32) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE time_trials
+            SET backend_version = $1
+            WHERE id = $2
+            "#,
+            backend_version,
+            tt_id
+        )
+        .execute(executor)
         .await?;
 
-        Ok(tts)
+        Ok(())
     }
 
+    /// Marks the time trial's current run as awaiting review, or clears that mark.
+    pub async fn set_flagged(executor: impl sqlx::PgExecutor<'_>, tt_id: Uuid, flagged: bool) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE time_trials
+            SET flagged_at = CASE WHEN $1 THEN NOW() END
+            WHERE id = $2
+            "#,
+            flagged,
+            tt_id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// `run_id` is the revalidation run that found the time trial invalid, if it wasn't invalidated by a moderator.
+    pub async fn invalidate(executor: impl sqlx::PgExecutor<'_>, tt_id: Uuid, reason: &str, run_id: Option<i32>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE time_trials
+            SET invalidated_at = NOW(), invalid_reason = $1, invalidated_by_run = $2, flagged_at = NULL
+            WHERE id = $3
+            "#,
+            reason,
+            run_id,
+            tt_id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes a time trial with its history, and records the removal in tt_removals. `reason` is None when the
+    /// owner withdraws the run. The user's entries in running events that came from this time trial are replaced by
+    /// their best remaining run in the event window, if they have one.
+    /// Returns the IDs of the removed non-best history entries, so their files can be deleted along with the
+    /// time trial's own.
     pub async fn delete(
         pool: &sqlx::PgPool,
         tt_id: Uuid,
-    ) -> Result<(), sqlx::Error> {
+        removed_by: i32,
+        reason: Option<&str>,
+    ) -> Result<Vec<Uuid>, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let history_ids = sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM time_trial_history
+            WHERE time_trial_id = $1 AND NOT is_best
+            "#,
+            tt_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // Frozen results are left alone; they're a record of how the event ended.
+        let affected_events = sqlx::query_scalar!(
+            r#"
+            DELETE FROM tt_event_entries en
+            USING tt_events e, time_trial_history h
+            WHERE en.event_id = e.id AND e.frozen_at IS NULL
+                AND en.history_id = h.id AND h.time_trial_id = $1
+            RETURNING en.event_id
+            "#,
+            tt_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if !affected_events.is_empty() {
+            sqlx::query!(
+                r#"
+                INSERT INTO tt_event_entries (event_id, user_id, car_author, car_name, history_id, tt_version, total_ticks, set_at)
+                SELECT DISTINCT ON (e.id) e.id, tt.user_id, tt.car_author, tt.car_name, h.id, h.tt_version, h.total_ticks, h.created_at
+                FROM tt_events e
+                JOIN tt_event_cars c ON c.event_id = e.id
+                JOIN time_trials tt ON tt.car_author = c.car_author AND tt.car_name = c.car_name
+                    AND tt.stage_author = e.stage_author AND tt.stage_name = e.stage_name
+                JOIN time_trial_history h ON h.time_trial_id = tt.id
+                    AND h.created_at >= e.starts_at AND h.created_at < e.ends_at
+                WHERE e.id = ANY($1)
+                    AND tt.user_id = (SELECT user_id FROM time_trials WHERE id = $2)
+                    AND tt.id <> $2
+                    -- Runs awaiting review, or rejected in review, were never entered
+                    AND NOT EXISTS (
+                        SELECT 1 FROM tt_flags f WHERE f.history_id = h.id AND f.approved IS DISTINCT FROM TRUE
+                    )
+                ORDER BY e.id, h.total_ticks ASC, h.created_at ASC
+                "#,
+                &affected_events,
+                tt_id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO tt_removals (time_trial_id, user_id, car_author, car_name, stage_author, stage_name, total_ticks,
+                removed_by, reason)
+            SELECT id, user_id, car_author, car_name, stage_author, stage_name, total_ticks, $2, $3
+            FROM time_trials
+            WHERE id = $1
+            "#,
+            tt_id,
+            removed_by,
+            reason
+        )
+        .execute(&mut *tx)
+        .await?;
+
         sqlx::query!(
             r#"
             DELETE FROM time_trials
@@ -166,9 +459,11 @@ impl TimeTrialEntry {
             "#,
             tt_id
         )
-        .execute(pool)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(())
+        tx.commit().await?;
+
+        Ok(history_ids)
     }
-}
\ No newline at end of file
+}
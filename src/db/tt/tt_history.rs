@@ -0,0 +1,202 @@
+use sqlx::types::{Uuid, time::PrimitiveDateTime};
+
+// Every accepted upload for a user/car/stage is recorded here, keyed by the time trial it belongs to.
+// Exactly one entry per time trial is marked as the best; its ghost is the time trial file itself
+// ("tt/{time_trial_id}.timetrial"). Every other entry keeps its ghost in "tt/history/{id}.timetrial".
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct TimeTrialHistoryEntry {
+    pub id: Uuid,
+    pub tt_version: i32,
+    pub total_ticks: i32,
+    pub is_best: bool,
+    pub created_at: PrimitiveDateTime,
+}
+impl TimeTrialHistoryEntry {
+    /// Records a run under `id`, which is chosen by the caller so that its ghost can be written before the entry
+    /// exists. If `is_best` is set, the previous best entry of the same time trial is unmarked.
+    pub async fn insert(
+        conn: &mut sqlx::PgConnection,
+        id: Uuid,
+        time_trial_id: Uuid,
+        tt_version: i32,
+        total_ticks: i32,
+        is_best: bool,
+    ) -> Result<Self, sqlx::Error> {
+        if is_best {
+            sqlx::query!(
+                r#"
+                UPDATE time_trial_history
+                SET is_best = FALSE
+                WHERE time_trial_id = $1 AND is_best
+                "#,
+                time_trial_id
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+
+        let res = sqlx::query_as!(
+            Self,
+            r#"
+            INSERT INTO time_trial_history (id, time_trial_id, tt_version, total_ticks, is_best)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, tt_version, total_ticks, is_best, created_at
+            "#,
+            id,
+            time_trial_id,
+            tt_version,
+            total_ticks,
+            is_best
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(res)
+    }
+
+    pub async fn get_best(executor: impl sqlx::PgExecutor<'_>, time_trial_id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let res = sqlx::query_as!(
+            Self,
+            r#"
+            SELECT id, tt_version, total_ticks, is_best, created_at
+            FROM time_trial_history
+            WHERE time_trial_id = $1 AND is_best
+            "#,
+            time_trial_id
+        )
+        .fetch_optional(executor)
+        .await?;
+
+        Ok(res)
+    }
+
+    /// Makes an existing entry the best of its time trial, e.g. a flagged run once it's approved.
+    pub async fn set_best(conn: &mut sqlx::PgConnection, time_trial_id: Uuid, id: Uuid) -> Result<(), sqlx::Error> {
+        // Two statements, since the best entry of a time trial is unique
+        sqlx::query!(
+            r#"
+            UPDATE time_trial_history
+            SET is_best = FALSE
+            WHERE time_trial_id = $1 AND is_best
+            "#,
+            time_trial_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE time_trial_history
+            SET is_best = TRUE
+            WHERE id = $1 AND time_trial_id = $2
+            "#,
+            id,
+            time_trial_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Deletes a single non-best entry, e.g. a rejected run. Its file is left to the caller.
+    pub async fn delete(executor: impl sqlx::PgExecutor<'_>, id: Uuid) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM time_trial_history
+            WHERE id = $1 AND NOT is_best
+            "#,
+            id
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get(pool: &sqlx::PgPool, id: Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let res = sqlx::query_as!(
+            Self,
+            r#"
+            SELECT id, tt_version, total_ticks, is_best, created_at
+            FROM time_trial_history
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    /// Oldest first
+    pub async fn get_for_time_trial(pool: &sqlx::PgPool, time_trial_id: Uuid) -> Result<Vec<Self>, sqlx::Error> {
+        let res = sqlx::query_as!(
+            Self,
+            r#"
+            SELECT id, tt_version, total_ticks, is_best, created_at
+            FROM time_trial_history
+            WHERE time_trial_id = $1
+            ORDER BY created_at ASC
+            "#,
+            time_trial_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    /// IDs of all non-best entries belonging to a user, i.e. every entry that has its own file in "tt/history".
+    pub async fn get_non_best_ids_for_user(pool: &sqlx::PgPool, user_id: i32) -> Result<Vec<Uuid>, sqlx::Error> {
+        let res = sqlx::query_scalar!(
+            r#"
+            SELECT h.id
+            FROM time_trial_history h
+            JOIN time_trials tt ON tt.id = h.time_trial_id
+            WHERE tt.user_id = $1 AND NOT h.is_best
+            "#,
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    /// Applies the retention policy to one time trial: only the newest `max_entries` non-best entries are kept, and
+    /// if `max_age_days` is set, non-best entries older than that are removed too. The best entry, entries whose
+    /// ghost is an event entry and entries awaiting review are never removed.
+    /// Returns the IDs of the removed entries so their files can be deleted.
+    pub async fn prune(executor: impl sqlx::PgExecutor<'_>, time_trial_id: Uuid, max_entries: i64, max_age_days: Option<i32>) -> Result<Vec<Uuid>, sqlx::Error> {
+        let res = sqlx::query_scalar!(
+            r#"
+            DELETE FROM time_trial_history
+            WHERE time_trial_id = $1
+                AND NOT is_best
+                -- Ghosts of event entries are kept for as long as the event is
+                AND NOT EXISTS (SELECT 1 FROM tt_event_entries en WHERE en.history_id = time_trial_history.id)
+                -- and those of runs awaiting review, so moderators can watch them
+                AND NOT EXISTS (SELECT 1 FROM tt_flags f WHERE f.history_id = time_trial_history.id AND f.reviewed_at IS NULL)
+                AND (
+                    id IN (
+                        SELECT id FROM time_trial_history
+                        WHERE time_trial_id = $1 AND NOT is_best
+                        ORDER BY created_at DESC
+                        OFFSET $2
+                    )
+                    OR ($3::int IS NOT NULL AND created_at < NOW() - make_interval(days => $3))
+                )
+            RETURNING id
+            "#,
+            time_trial_id,
+            max_entries,
+            max_age_days
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(res)
+    }
+}
\ No newline at end of file
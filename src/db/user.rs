@@ -2,6 +2,12 @@ use sqlx::types::time::PrimitiveDateTime;
 use std::fmt::Display;
 use subtle::ConstantTimeEq;
 
+use crate::db::archive::archive_item::ArchiveItem;
+
+/// Username of the placeholder account that anonymised items are handed over to when their owner deletes
+/// their account. The brackets make it impossible to register through `validate_username`.
+pub const DELETED_USER_USERNAME: &str = "[deleted]";
+
 #[derive(sqlx::FromRow, Debug)]
 pub struct User {
     /// ID sequentially assigned by DB
@@ -149,6 +155,54 @@ impl User {
         Ok(id)
     }
 
+    /// Returns the ID of the placeholder account used for anonymised items, creating it if it doesn't exist yet.
+    /// The account has no password and no oauth link, so nobody can log into it.
+    pub async fn get_or_create_deleted_user(pool: &sqlx::PgPool) -> Result<i32, sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO users(username)
+            VALUES($1)
+            ON CONFLICT (username) DO NOTHING
+            "#,
+            DELETED_USER_USERNAME
+        )
+        .execute(pool)
+        .await?;
+
+        let id = sqlx::query_scalar!(
+            r#"
+            SELECT id FROM users WHERE username = $1
+            "#,
+            DELETED_USER_USERNAME
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Removes the user row, after handing their archive items over to `items_to` so that they keep an owner.
+    /// Both happen in one transaction, so a failed deletion doesn't leave the items transferred. Tokens, oauth
+    /// links and time trials are removed by the database (ON DELETE CASCADE).
+    /// Returns the number of items transferred.
+    pub async fn delete(pool: &sqlx::PgPool, user_id: i32, items_to: i32) -> Result<u64, sqlx::Error> {
+        let mut tx = pool.begin().await?;
+
+        let transferred = ArchiveItem::transfer_ownership(&mut tx, user_id, items_to).await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM users WHERE id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(transferred)
+    }
+
     /// Retrieve a user by username
     ///
     /// Note: This does NOT verify the password
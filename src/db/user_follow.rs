@@ -0,0 +1,67 @@
+use sqlx::PgPool;
+
+/// Returns false if the user was already following them.
+pub async fn follow(pool: &PgPool, follower_id: i32, followee_id: i32) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        r#"
+        INSERT INTO user_follows (follower_id, followee_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        follower_id,
+        followee_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+/// Returns false if the user wasn't following them.
+pub async fn unfollow(pool: &PgPool, follower_id: i32, followee_id: i32) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query!(
+        r#"
+        DELETE FROM user_follows
+        WHERE follower_id = $1 AND followee_id = $2
+        "#,
+        follower_id,
+        followee_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected() == 1)
+}
+
+/// Whether both users follow each other.
+pub async fn are_friends(pool: &PgPool, user_a: i32, user_b: i32) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) = 2 AS "friends!"
+        FROM user_follows
+        WHERE (follower_id = $1 AND followee_id = $2) OR (follower_id = $2 AND followee_id = $1)
+        "#,
+        user_a,
+        user_b
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res)
+}
+
+/// Usernames of the users `user_id` follows, alphabetically.
+pub async fn get_following(pool: &PgPool, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT u.username
+        FROM user_follows f
+        JOIN users u ON u.id = f.followee_id
+        WHERE f.follower_id = $1
+        ORDER BY u.username
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,26 @@
+use sqlx::PgPool;
+
+// Roles are granted by hand in the database; see the tt_events migration.
+
+/// May create time trial events, and do anything a moderator can.
+pub const ROLE_ADMIN: &str = "admin";
+/// Reviews flagged time trials.
+pub const ROLE_MODERATOR: &str = "moderator";
+
+/// Roles that may moderate time trials.
+pub const MODERATOR_ROLES: &[&str] = &[ROLE_MODERATOR, ROLE_ADMIN];
+
+/// Whether the user has at least one of `roles`.
+pub async fn user_has_role(pool: &PgPool, user_id: i32, roles: &[&str]) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM user_roles WHERE user_id = $1 AND role = ANY($2)) AS "exists!"
+        "#,
+        user_id,
+        roles as &[&str]
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(res)
+}
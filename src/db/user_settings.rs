@@ -0,0 +1,63 @@
+use sqlx::PgPool;
+
+/// Who may download a user's ghosts. The user themselves always can.
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GhostVisibility {
+    #[default]
+    Public,
+    /// Users who follow the owner and whom the owner follows back
+    Friends,
+    Private,
+}
+impl GhostVisibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GhostVisibility::Public => "public",
+            GhostVisibility::Friends => "friends",
+            GhostVisibility::Private => "private",
+        }
+    }
+}
+impl TryFrom<&str> for GhostVisibility {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "public" => Ok(GhostVisibility::Public),
+            "friends" => Ok(GhostVisibility::Friends),
+            "private" => Ok(GhostVisibility::Private),
+            _ => Err(()),
+        }
+    }
+}
+
+pub async fn get_ghost_visibility(pool: &PgPool, user_id: i32) -> Result<GhostVisibility, sqlx::Error> {
+    let res = sqlx::query_scalar!(
+        r#"
+        SELECT ghost_visibility FROM user_settings WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    // The column is constrained to valid values; anything else is treated as private to be safe.
+    Ok(res.map_or(GhostVisibility::default(), |v| GhostVisibility::try_from(v.as_str()).unwrap_or(GhostVisibility::Private)))
+}
+
+pub async fn set_ghost_visibility(pool: &PgPool, user_id: i32, visibility: GhostVisibility) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_settings (user_id, ghost_visibility)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET ghost_visibility = EXCLUDED.ghost_visibility
+        "#,
+        user_id,
+        visibility.as_str()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
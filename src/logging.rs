@@ -0,0 +1,77 @@
+// Logging goes through `tracing`. Every request is given an ID (or keeps the one it came with in `x-request-id`),
+// which is recorded on the request's span, returned in the response headers and added to JSON error replies,
+// so a user's report of a failed request can be matched to the logs.
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LogFormat;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Error replies are small; anything larger than this is passed through untouched.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// Installs the global subscriber. The level is taken from `RUST_LOG`, defaulting to `info`.
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+}
+
+fn request_id(req: &Request) -> Option<&str> {
+    req.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok())
+}
+
+pub fn make_request_span(req: &Request) -> Span {
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        path = %req.uri().path(),
+        request_id = request_id(req).unwrap_or("-"),
+    )
+}
+
+/// Adds `"request_id"` to JSON error replies.
+pub async fn add_request_id_to_errors(req: Request, next: Next) -> Response {
+    let Some(request_id) = request_id(&req).map(str::to_string) else {
+        return next.run(req).await;
+    };
+    let res = next.run(req).await;
+
+    let is_json = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if !is_json || !(res.status().is_client_error() || res.status().is_server_error()) {
+        return res;
+    }
+
+    let (mut parts, body) = res.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_ERROR_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to read error reply to add the request ID");
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut reply)) => {
+            reply.insert("request_id".to_string(), request_id.into());
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(serde_json::Value::Object(reply).to_string())
+        }
+        _ => Body::from(bytes),
+    };
+    Response::from_parts(parts, body)
+}
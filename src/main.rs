@@ -1,92 +1,211 @@
 use std::env::{self, VarError};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use axum::Router;
-use axum::routing::{get, patch, post};
+use axum::middleware;
+use axum::routing::{delete, get, patch, post};
 use reqwest::Client;
-use sqlx::postgres::{PgPool, PgPoolOptions};
-use tokio::sync::Mutex;
+use sqlx::{Connection, postgres::{PgConnectOptions, PgConnection, PgPool, PgPoolOptions}};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{Level, error, info, warn};
 
 use crate::archive::ensure_default_dirs_exist;
 use crate::archive::index::index_archive;
 use crate::archive::parse::parse_line;
 use crate::config::load_config;
+use crate::db::migrate::run_migrations;
+use crate::db::tt::event::TimeTrialEvent;
 use crate::db::user::User;
-use crate::ffi::{SimulateTimeTrialArgs, nfmw_simulate_tt};
+use crate::metrics::{Metrics, track_requests};
 use crate::route::oauth2::discord;
+use crate::tt::ensure_tt_dirs_exist;
+use crate::tt::backend::{BackendKind, create_backend};
+use crate::tt::pool::SimulationPool;
+use crate::tt::revalidate::revalidate_time_trials;
 
 mod archive;
 mod config;
 mod crypto;
 mod db;
+#[cfg(feature = "ffi")]
 mod ffi;
+mod logging;
+mod metrics;
 mod route;
 mod state;
 mod tt;
 
 #[tokio::main]
 async fn main() {
-    let config = load_config();
-    println!("Filestore path: {}", config.filestore);
-
+    // Loaded first so that NFMW_* settings in .env apply too.
     let _ = dotenvy::dotenv();
 
+    // Logging is configured by the config, so problems with it can only be printed.
+    let config = load_config().unwrap_or_else(|e| {
+        eprintln!("Failed to load configuration: {e}");
+        std::process::exit(1);
+    });
+    logging::init(config.log_format);
+    info!(filestore = %config.filestore, "loaded configuration");
+    if config.tt.backend == BackendKind::Fake {
+        warn!("the fake time trial backend is active: it accepts any well-formed file as a valid run, so don't expose this server to players");
+    }
+
     let db_url = env::var("DATABASE_URL").or_else(|_| {
         env::var("DATABASE_URL")
     }).unwrap();
 
+    let base_options = PgConnectOptions::from_str(&db_url).expect("Invalid DATABASE_URL");
+    let mut connect_options = base_options.clone();
+    if let Some(timeout) = config.database.statement_timeout_ms {
+        connect_options = connect_options.options([("statement_timeout", timeout.to_string())]);
+    }
+
     let pool = PgPoolOptions::new()
-        .max_connections(1)
-        .connect(&db_url)
+        .max_connections(config.database.max_connections)
+        .min_connections(config.database.min_connections)
+        .acquire_timeout(Duration::from_secs(config.database.acquire_timeout_secs))
+        .connect_with(connect_options)
         .await
         .expect("Failed to create Postgres connection pool");
 
-    println!(
-        "Connected to database on {db_url}"
-    );
+    // Logged without the URL itself, which may hold a password.
+    let connected = pool.connect_options();
+    info!(host = connected.get_host(), port = connected.get_port(), database = connected.get_database(), "connected to database");
+
+    // Migrations run without the statement timeout, which is meant for request queries.
+    let mut migration_conn = PgConnection::connect_with(&base_options)
+        .await
+        .expect("Failed to connect to Postgres for migrations");
+    if let Err(e) = run_migrations(&mut migration_conn, config.database.migrations).await {
+        panic!("Failed to migrate database: {e}");
+    }
+    if let Err(e) = migration_conn.close().await {
+        warn!(error = %e, "failed to close the migration connection");
+    }
 
     // TODO: for now, fully regenerate archive db on restart,
     // but in future we can probably do some sanity checks
     // to see if this is necessary as the size of the archive grows
-    let state = Arc::new(Mutex::new(state::State {
+    let state = Arc::new(state::State {
         db_pool: pool,
-        index_state: state::IndexState::Regenerating,
-        config: load_config(),
-        req_client: Client::new()
-    }));
+        index_state: state::SharedIndexState::new(state::IndexState::Regenerating),
+        sim_pool: SimulationPool::new(&config.tt),
+        tt_backend: create_backend(config.tt.backend).expect("Failed to create time trial backend"),
+        config: config.clone(),
+        req_client: Client::new(),
+        metrics: Metrics::new()
+    });
+
+    ensure_default_dirs_exist(&state.config.filestore).unwrap();
+    ensure_tt_dirs_exist(&state.config.filestore).unwrap();
+
+    let index_started = Instant::now();
+    let index_error = match tokio::spawn(index_archive(state.clone())).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(e) => Some(e.to_string()),
+    };
+    state.metrics.index_duration.set(index_started.elapsed().as_secs_f64());
+    if let Some(e) = index_error {
+        error!(error = %e, "failed to index archive");
+        state.metrics.index_errors.inc();
+        // Searching stays disabled, and readiness reports it, until the index is regenerated.
+        state.index_state.set(state::IndexState::Unhealthy);
+    }
 
-    ensure_default_dirs_exist(&state.lock().await.config.filestore).unwrap();
+    // Runs in the background; uploads keep working meanwhile, and leaderboards update as runs are invalidated.
+    let c = state.clone();
+    tokio::spawn(async {
+        if let Err(e) = revalidate_time_trials(c).await {
+            error!(error = %e, "failed to re-validate time trials")
+        }
+    });
 
+    // Events are also frozen on demand when their leaderboard is requested; this catches the ones nobody looks at.
     let c = state.clone();
-    let idx = tokio::spawn(async {
-        let index_result = index_archive(c).await;
-        if let Err(e) = index_result {
-            eprintln!("Failed to index database! {e}")
+    tokio::spawn(async move {
+        let pool = c.db_pool.clone();
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            match TimeTrialEvent::freeze_ended(&pool).await {
+                Ok(frozen) if !frozen.is_empty() => info!(events = ?frozen, "froze results of time trial events"),
+                Ok(_) => {}
+                Err(e) => error!(error = %e, "failed to freeze time trial events"),
+            }
         }
-    }).await;
-    if let Err(e) = idx {
-        eprintln!("Failed to index database! {e}")
+    });
+
+    if let Some(metrics_port) = config.metrics_port {
+        let metrics_router = Router::new()
+            .route("/metrics", get(route::metrics::metrics))
+            .with_state(state.clone());
+        let metrics_addr = format!("0.0.0.0:{metrics_port}");
+        let metrics_listener = tokio::net::TcpListener::bind(&metrics_addr)
+            .await
+            .expect("Failed to bind metrics address");
+        info!(addr = %metrics_addr, "serving metrics");
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(metrics_listener, metrics_router).await {
+                error!(error = %e, "metrics server failed");
+            }
+        });
     }
 
     let axum_router = Router::new()
-        .route("/", get(route::root))
+        .route("/", get(route::health::live))
+        .route("/health/live", get(route::health::live))
+        .route("/health/ready", get(route::health::ready))
         .route(
             "/create_account",
             post(route::local_create_account::create_account),
         )
         .route("/login", post(route::local_login::login))
+        .route("/account/export", post(route::account::export_data::export_data))
+        .route("/account/delete", delete(route::account::delete_account::delete_account))
+        .route("/user/profile", post(route::user::profile::profile))
+        .route("/user/settings", post(route::user::settings::settings))
+        .route("/user/follow", post(route::user::follow::follow))
+        .route("/user/unfollow", post(route::user::follow::unfollow))
         .route("/archive/create_stage_piece", patch(route::archive::create_stage_piece::create_stage_piece))
         .route("/discord/login", post(discord::discord_login::login))
         .route("/discord/create_account", post(discord::discord_create_account::create_account))
         .route("/tt/search", post(route::tt::search_tt::search_tt))
         .route("/tt/upload", patch(route::tt::upload_tt::upload_tt))
         .route("/tt/fetch", post(route::tt::fetch_tt::fetch_tt))
+        .route("/tt/fetch_batch", post(route::tt::fetch_batch::fetch_batch))
+        .route("/tt/leaderboard", post(route::tt::leaderboard::leaderboard))
+        .route("/tt/history", post(route::tt::tt_history::tt_history))
+        .route("/tt/history/fetch", post(route::tt::fetch_history::fetch_history))
+        .route("/tt/delete", delete(route::tt::delete_tt::delete_tt))
+        .route("/tt/revalidation", post(route::tt::revalidation_report::revalidation_report))
+        .route("/tt/events/create", post(route::tt::event::create_event::create_event))
+        .route("/tt/events/list", post(route::tt::event::list_events::list_events))
+        .route("/tt/events/leaderboard", post(route::tt::event::event_leaderboard::event_leaderboard))
+        .route("/tt/moderation/queue", post(route::tt::moderation::flag_queue::flag_queue))
+        .route("/tt/moderation/review", post(route::tt::moderation::review_flag::review_flag))
+        .route("/tt/moderation/remove", post(route::tt::moderation::remove_tt::remove_tt))
+        .route_layer(middleware::from_fn_with_state(state.clone(), track_requests))
+        // Outermost last: the ID is assigned first, so the span and error replies can use it.
+        .layer(middleware::from_fn(logging::add_request_id_to_errors))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(logging::make_request_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
         .with_state(state);
 
     let addr = format!("0.0.0.0:{}", config.port);
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
         .expect("Failed to bind to address");
+    info!(%addr, "listening");
 
     axum::serve(listener, axum_router)
         .await
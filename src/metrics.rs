@@ -0,0 +1,152 @@
+// Prometheus metrics, served at /metrics. Counters and histograms are updated where things happen; gauges that
+// mirror other state (pool usage, item counts) are refreshed when the metrics are scraped.
+
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use prometheus_client::{
+    encoding::{EncodeLabelSet, EncodeLabelValue},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{Histogram, exponential_buckets},
+    },
+    registry::Registry,
+};
+
+use crate::{state::ThreadSafeState, tt::pool::SimulationPoolError};
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct RequestLabels {
+    pub method: String,
+    /// The route pattern, e.g. `/tt/fetch`, so that paths with parameters don't each get their own series.
+    pub route: String,
+    pub status: u16,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum SimulationOutcome {
+    /// Reproduced and passed the plausibility checks.
+    Accepted,
+    /// Reproduced, but held for review.
+    Flagged,
+    /// Didn't reproduce, or didn't match its stage.
+    Rejected,
+    QueueFull,
+    TimedOut,
+    Failed,
+}
+
+impl From<&SimulationPoolError> for SimulationOutcome {
+    fn from(e: &SimulationPoolError) -> Self {
+        match e {
+            SimulationPoolError::QueueFull => SimulationOutcome::QueueFull,
+            SimulationPoolError::TimedOut => SimulationOutcome::TimedOut,
+            SimulationPoolError::Failed => SimulationOutcome::Failed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct SimulationLabels {
+    pub outcome: SimulationOutcome,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ItemTypeLabels {
+    pub r#type: String,
+}
+
+type HistogramFamily<S> = Family<S, Histogram, fn() -> Histogram>;
+
+pub struct Metrics {
+    registry: Registry,
+    pub requests: HistogramFamily<RequestLabels>,
+    pub upload_size: Histogram,
+    pub simulation_duration: Histogram,
+    pub simulations: Family<SimulationLabels, Counter>,
+    pub archive_items: Family<ItemTypeLabels, Gauge>,
+    pub db_pool_connections: Gauge,
+    pub db_pool_idle_connections: Gauge,
+    pub db_pool_max_connections: Gauge,
+    pub index_duration: Gauge<f64, std::sync::atomic::AtomicU64>,
+    pub index_errors: Counter,
+    pub index_regenerating: Gauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let metrics = Metrics {
+            registry: Registry::default(),
+            requests: Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.005, 2.0, 12))),
+            // 1 KiB to 256 MiB
+            upload_size: Histogram::new(exponential_buckets(1024.0, 4.0, 10)),
+            simulation_duration: Histogram::new(exponential_buckets(0.01, 2.0, 12)),
+            simulations: Family::default(),
+            archive_items: Family::default(),
+            db_pool_connections: Gauge::default(),
+            db_pool_idle_connections: Gauge::default(),
+            db_pool_max_connections: Gauge::default(),
+            index_duration: Gauge::default(),
+            index_errors: Counter::default(),
+            index_regenerating: Gauge::default(),
+        };
+        metrics.register()
+    }
+
+    fn register(mut self) -> Self {
+        let r = &mut self.registry;
+        r.register("http_request_duration_seconds", "Time taken to handle requests, by route", self.requests.clone());
+        r.register("tt_upload_size_bytes", "Size of uploaded time trial files", self.upload_size.clone());
+        r.register(
+            "tt_simulation_duration_seconds",
+            "Time taken to simulate uploaded time trials, excluding time queued",
+            self.simulation_duration.clone(),
+        );
+        r.register("tt_simulations", "Uploaded time trials by simulation outcome", self.simulations.clone());
+        r.register("archive_items", "Items in the archive, by type", self.archive_items.clone());
+        r.register("db_pool_connections", "Open database connections", self.db_pool_connections.clone());
+        r.register("db_pool_idle_connections", "Idle database connections", self.db_pool_idle_connections.clone());
+        r.register("db_pool_max_connections", "Configured maximum database connections", self.db_pool_max_connections.clone());
+        r.register("archive_index_duration_seconds", "Duration of the last archive indexing run", self.index_duration.clone());
+        r.register("archive_index_errors", "Archive indexing runs that failed", self.index_errors.clone());
+        r.register("archive_index_regenerating", "1 while the archive index is being regenerated", self.index_regenerating.clone());
+        self
+    }
+
+    pub fn record_simulation(&self, outcome: SimulationOutcome) {
+        self.simulations.get_or_create(&SimulationLabels { outcome }).inc();
+    }
+
+    /// Renders everything in the OpenMetrics text format.
+    pub fn encode(&self) -> Result<String, std::fmt::Error> {
+        let mut out = String::new();
+        prometheus_client::encoding::text::encode(&mut out, &self.registry)?;
+        Ok(out)
+    }
+}
+
+/// Records the latency of every routed request. Applied with `route_layer`, so requests that match no route
+/// aren't counted.
+pub async fn track_requests(State(state): State<ThreadSafeState>, req: Request, next: Next) -> Response {
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| req.uri().path().to_string(), |p| p.as_str().to_string());
+
+    let started = Instant::now();
+    let res = next.run(req).await;
+
+    state
+        .metrics
+        .requests
+        .get_or_create(&RequestLabels { method, route, status: res.status().as_u16() })
+        .observe(started.elapsed().as_secs_f64());
+    res
+}
@@ -0,0 +1,117 @@
+// Deletes the authenticated user's account. Before the user row is removed, the user's archive items are either
+// transferred to another account or handed over to the placeholder "[deleted]" account, so that they keep an owner.
+// Time trials are removed along with the account, including their files in the filestore.
+
+use axum::{Json, extract::State, http::{HeaderMap, StatusCode}};
+use serde_json::json;
+
+use crate::{
+    db::{
+        token::UserToken,
+        tt::{tt_entry::TimeTrialEntry, tt_history::TimeTrialHistoryEntry}, user::User,
+    },
+    route::db_error,
+    state::ThreadSafeState,
+    tt::{delete_tt_history_files, get_tt_file_path},
+};
+
+#[derive(serde::Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemDisposition {
+    /// Hand the items over to the account named in `transfer_to`
+    Transfer,
+    /// Hand the items over to the placeholder "[deleted]" account
+    Anonymise,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DeleteAccountPayload {
+    /// Must match the username of the authenticated user, to guard against accidental deletion
+    pub confirm_username: String,
+    pub items: ItemDisposition,
+    /// Username of the account that receives the items; required when `items` is "transfer"
+    pub transfer_to: Option<String>,
+}
+
+pub async fn delete_account(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(payload): Json<DeleteAccountPayload>,
+) -> axum::response::Result<(StatusCode, Json<serde_json::Value>)> {
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"status": "missing or invalid Authorization header"})),
+        ))?;
+    let pool = &state.db_pool;
+
+    let user_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
+
+    let user = User::get_by_user_id(pool, user_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "user not found"}))))?;
+
+    if user.username != payload.confirm_username {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"status": "confirm_username does not match the account being deleted"})),
+        ));
+    }
+
+    let new_owner_id = match payload.items {
+        ItemDisposition::Transfer => {
+            let transfer_to = payload.transfer_to.ok_or((
+                StatusCode::BAD_REQUEST,
+                Json(json!({"status": "transfer_to must be provided when transferring items"})),
+            ))?;
+
+            let id = User::get_id_from_username(pool, &transfer_to)
+                .await
+                .map_err(db_error)?
+                .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "transfer_to user not found"}))))?;
+
+            if id == user_id {
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({"status": "cannot transfer items to the account being deleted"})),
+                ));
+            }
+
+            id
+        }
+        ItemDisposition::Anonymise => User::get_or_create_deleted_user(pool)
+            .await
+            .map_err(db_error)?,
+    };
+
+    // The rows are removed by the cascade on users, but the files have to be removed by hand.
+    let tts = TimeTrialEntry::filter_by_user(pool, user_id)
+        .await
+        .map_err(db_error)?;
+    let history_ids = TimeTrialHistoryEntry::get_non_best_ids_for_user(pool, user_id)
+        .await
+        .map_err(db_error)?;
+
+    let transferred = User::delete(pool, user_id, new_owner_id)
+        .await
+        .map_err(db_error)?;
+
+    for tt in tts {
+        if let Err(e) = tokio::fs::remove_file(get_tt_file_path(&state.config.filestore, tt.id)).await {
+            tracing::warn!(tt_id = %tt.id, user_id, error = %e, "failed to remove TT file of deleted user");
+        }
+    }
+    delete_tt_history_files(&state.config.filestore, &history_ids).await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"status": "account deleted", "items_transferred": transferred})),
+    ))
+}
\ No newline at end of file
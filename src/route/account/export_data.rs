@@ -0,0 +1,196 @@
+// Returns everything the server stores about the authenticated user as a single JSON document.
+// Time trial files are included inline, base64 encoded, so the export is self-contained.
+
+use axum::{Json, extract::State, http::{HeaderMap, StatusCode}};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde_json::json;
+
+use crate::{
+    archive::ArchiveItemKey,
+    db::{
+        archive::archive_item::ArchiveItem, oauth2::discord_oauth2::DiscordOauth2AccountEntry,
+        token::UserToken, tt::{tt_entry::TimeTrialEntry, tt_history::TimeTrialHistoryEntry}, user::User,
+        user_follow::get_following, user_settings::{GhostVisibility, get_ghost_visibility},
+    },
+    route::db_error,
+    state::ThreadSafeState,
+    tt::{get_tt_file_path, read_tt_file_at},
+};
+
+#[derive(serde::Serialize)]
+pub struct ExportProfile {
+    pub id: i32,
+    pub username: String,
+    /// ISO 8601 format
+    pub created_at: String,
+    pub has_local_password: bool,
+    pub must_change_password: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct ExportSession {
+    pub token_id: i32,
+}
+
+#[derive(serde::Serialize)]
+pub struct ExportArchiveItem {
+    pub author: String,
+    pub name: String,
+    /// Legacy uuid, if the item has one
+    pub id: Option<String>,
+    pub r#type: String,
+    /// ISO 8601 format
+    pub created_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ExportTimeTrialRun {
+    pub id: String,
+    pub tt_version: i32,
+    pub total_ticks: i32,
+    pub is_best: bool,
+    /// ISO 8601 format
+    pub created_at: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ExportTimeTrial {
+    pub id: String,
+    pub car: ArchiveItemKey,
+    pub stage: ArchiveItemKey,
+    pub tt_version: i32,
+    pub total_ticks: i32,
+    /// ISO 8601 format
+    pub created_at: String,
+    /// Base64 encoded .timetrial file of the best run, or None if the file is missing from the filestore
+    pub file: Option<String>,
+    /// Every recorded run, oldest first. Ghosts of superseded runs are not included.
+    pub history: Vec<ExportTimeTrialRun>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ExportData {
+    pub profile: ExportProfile,
+    pub sessions: Vec<ExportSession>,
+    pub discord_user_id: Option<i64>,
+    pub archive_items: Vec<ExportArchiveItem>,
+    pub time_trials: Vec<ExportTimeTrial>,
+    pub ghost_visibility: GhostVisibility,
+    /// Usernames of the users being followed
+    pub following: Vec<String>,
+}
+
+pub async fn export_data(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+) -> axum::response::Result<(StatusCode, Json<ExportData>)> {
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((
+            StatusCode::UNAUTHORIZED,
+            Json(json!({"status": "missing or invalid Authorization header"})),
+        ))?;
+    let pool = &state.db_pool;
+
+    let user_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
+
+    let user = User::get_by_user_id(pool, user_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "user not found"}))))?;
+
+    let sessions = UserToken::get_by_user_id(pool, user_id)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|t| ExportSession { token_id: t.token_id })
+        .collect();
+
+    let discord_user_id = DiscordOauth2AccountEntry::lookup_user_id(pool, user_id)
+        .await
+        .map_err(db_error)?
+        .map(|d| d.discord_user_id);
+
+    let archive_items = ArchiveItem::get_by_owner(pool, user_id)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|i| ExportArchiveItem {
+            author: i.author,
+            name: i.name,
+            id: i.legacy_id.map(|id| id.hyphenated().to_string()),
+            r#type: i.r#type,
+            created_at: i.created_at.map_or_else(String::new, |dt| dt.to_string()),
+        })
+        .collect();
+
+    let raw_tts = TimeTrialEntry::filter_by_user(pool, user_id)
+        .await
+        .map_err(db_error)?;
+
+    let mut time_trials = Vec::new();
+    for tt in raw_tts {
+        // A missing file shouldn't block the export of everything else.
+        let file = read_tt_file_at(&get_tt_file_path(&state.config.filestore, tt.id))
+            .await
+            .ok()
+            .map(|b| BASE64_STANDARD.encode(b));
+
+        let history = TimeTrialHistoryEntry::get_for_time_trial(pool, tt.id)
+            .await
+            .map_err(db_error)?
+            .into_iter()
+            .map(|h| ExportTimeTrialRun {
+                id: h.id.to_string(),
+                tt_version: h.tt_version,
+                total_ticks: h.total_ticks,
+                is_best: h.is_best,
+                created_at: h.created_at.to_string(),
+            })
+            .collect();
+
+        time_trials.push(ExportTimeTrial {
+            id: tt.id.to_string(),
+            car: tt.car(),
+            stage: tt.stage(),
+            tt_version: tt.tt_version,
+            total_ticks: tt.total_ticks,
+            created_at: tt.created_at.map_or_else(String::new, |dt| dt.to_string()),
+            file,
+            history,
+        });
+    }
+
+    let ghost_visibility = get_ghost_visibility(pool, user_id)
+        .await
+        .map_err(db_error)?;
+    let following = get_following(pool, user_id)
+        .await
+        .map_err(db_error)?;
+
+    let profile = ExportProfile {
+        id: user.id,
+        username: user.username,
+        created_at: user.created_at.map_or_else(String::new, |dt| dt.to_string()),
+        has_local_password: user.phash.is_some(),
+        must_change_password: user.must_change_password.unwrap_or(false),
+    };
+
+    Ok((
+        StatusCode::OK,
+        Json(ExportData {
+            profile,
+            sessions,
+            discord_user_id,
+            archive_items,
+            time_trials,
+            ghost_visibility,
+            following,
+        }),
+    ))
+}
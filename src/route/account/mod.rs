@@ -0,0 +1,2 @@
+pub mod delete_account;
+pub mod export_data;
\ No newline at end of file
@@ -4,18 +4,17 @@ use axum::{Json, extract::{Multipart, State}, http::{HeaderMap, StatusCode}};
 use serde_json::json;
 use sqlx::types::{Uuid, uuid::Version};
 
-use crate::{archive::{ArchiveItemType, parse::parse_file}, db::{archive::{archive_item::ArchiveItem, archive_item_tag::ArchiveItemTag, archive_tag::ArchiveTag, archive_tag_ownership::user_can_assign_tag}, token::UserToken}, state::ThreadSafeState};
+use crate::{archive::{ArchiveItemType, parse::parse_file}, db::{archive::{archive_item::ArchiveItem, archive_item_tag::ArchiveItemTag, archive_tag::ArchiveTag, archive_tag_ownership::user_can_assign_tag}, token::UserToken}, route::db_error, state::ThreadSafeState};
 
 pub async fn create_stage_piece(State(state): State<ThreadSafeState>, headers: HeaderMap, mut multipart: Multipart) -> axum::response::Result<(StatusCode, Json<serde_json::Value>)> {
     let auth = headers.get("authorization").ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "no authorization token provided"}))))?;
-    let lock = state.lock().await;
 
     let authenticated_user = UserToken::get_user_by_token(
-        &lock.db_pool, 
+        &state.db_pool, 
         auth.to_str().map_err(|e| 
             (StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid authorization token"})))
         )?).await
-        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"status": "internal database error"}))))?
+        .map_err(db_error)?
         .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid authorization token"}))))?;
 
     let r#type: ArchiveItemType = ArchiveItemType::StagePiece;
@@ -63,7 +62,7 @@ pub async fn create_stage_piece(State(state): State<ThreadSafeState>, headers: H
         return Ok((StatusCode::BAD_REQUEST, Json(json!({"status": "item must have an author"}))));
     }
 
-    let path = format!("{}/{}/{}/{}.txt", lock.config.filestore, r#type.dir_name(), parsed.author.clone().unwrap(), id.hyphenated().to_string());
+    let path = format!("{}/{}/{}/{}.txt", state.config.filestore, r#type.dir_name(), parsed.author.clone().unwrap(), id.hyphenated().to_string());
 
     let ex = std::fs::exists(&path);
     if let Ok(e) = ex && e == false {
@@ -76,23 +75,24 @@ pub async fn create_stage_piece(State(state): State<ThreadSafeState>, headers: H
         return Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"status": format!("internal filesystem error on item save: {e}")}))));
     }
 
+    // The file is still stored under a UUID, which doubles as the legacy ID for older clients.
     let item = ArchiveItem {
-        archive_item_id: id,
+        author: parsed.author.unwrap(),
+        name: parsed.name,
         path,
         r#type: r#type.to_string(),
-        name: parsed.name,
         created_at: None,
-        author: parsed.author,
-        owner_user_id: authenticated_user.user_id
+        owner_user_id: Some(authenticated_user.user_id),
+        legacy_id: Some(id)
     };
-    item.insert(&lock.db_pool).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"status": "internal database error on item save"}))))?;
+    item.insert(&state.db_pool).await.map_err(db_error)?;
 
     for tag in parsed.tags {
-        let id = ArchiveTag::get_id_from_name(&lock.db_pool, tag.clone()).await
-            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"status": "internal database error on tag lookup"}))))?
+        let id = ArchiveTag::get_id_from_name(&state.db_pool, tag.clone()).await
+            .map_err(db_error)?
             .ok_or((StatusCode::BAD_REQUEST, Json(json!({"status": format!("provided tag {tag} does not exist")}))))?;
 
-        let user_can_assign = user_can_assign_tag(&lock.db_pool, id, authenticated_user.user_id).await
+        let user_can_assign = user_can_assign_tag(&state.db_pool, id, authenticated_user.user_id).await
             .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"status": format!("{e}")}))))?;
 
         if !user_can_assign {
@@ -100,13 +100,14 @@ pub async fn create_stage_piece(State(state): State<ThreadSafeState>, headers: H
         }
 
         let relation = ArchiveItemTag {
-            archive_item_id: item.archive_item_id,
+            item_author: item.author.clone(),
+            item_name: item.name.clone(),
             tag_id: id
         };
 
-        relation.insert(&lock.db_pool).await
-            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"status": "internal database error on tag relation insertion"}))))?;
+        relation.insert(&state.db_pool).await
+            .map_err(db_error)?;
     }
 
-    Ok((StatusCode::OK, Json(serde_json::json!({"status": format!("stage piece created", id.hyphenated().to_string()), "id": id.hyphenated().to_string()}))))
+    Ok((StatusCode::OK, Json(serde_json::json!({"status": "stage piece created", "id": id.hyphenated().to_string()}))))
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+use std::{sync::Mutex, time::{Duration, Instant}};
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use serde::Serialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    db::tt::tt_entry::TimeTrialEntry,
+    state::{IndexState, ThreadSafeState},
+    tt::{get_tt_file_path, read_tt_file_at},
+};
+
+/// Each check gives up after this long, so a hung dependency can't hang the probe.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// The backend check replays a ghost, so its result is reused for a while rather than redone on every probe.
+const BACKEND_CHECK_TTL: Duration = Duration::from_secs(10);
+
+static LAST_BACKEND_CHECK: Mutex<Option<(Instant, Result<(), String>)>> = Mutex::new(None);
+
+#[derive(Serialize)]
+pub struct ReadinessResponse {
+    pub status: &'static str,
+    pub checks: ReadinessChecks,
+}
+
+/// "ok", or what went wrong. The index reports its state instead; anything but "healthy" means not ready.
+#[derive(Serialize)]
+pub struct ReadinessChecks {
+    pub database: String,
+    pub filestore: String,
+    pub backend: String,
+    pub index: &'static str,
+}
+
+/// Liveness: the process is up and serving requests. Nothing else is checked, so a struggling dependency
+/// doesn't get the server restarted.
+pub async fn live() -> Json<serde_json::Value> {
+    Json(json!({"status": "alive"}))
+}
+
+/// Readiness: the server can handle requests right now. Returns 503 with the failing checks otherwise.
+pub async fn ready(State(state): State<ThreadSafeState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let (database, filestore, backend) = tokio::join!(
+        with_timeout(check_database(&state)),
+        with_timeout(check_filestore(&state.config.filestore)),
+        with_timeout(check_backend(&state)),
+    );
+    let index = state.index_state.get();
+
+    let ready = database.is_ok() && filestore.is_ok() && backend.is_ok() && index == IndexState::Healthy;
+    let checks = ReadinessChecks {
+        database: database.err().unwrap_or_else(|| "ok".to_string()),
+        filestore: filestore.err().unwrap_or_else(|| "ok".to_string()),
+        backend: backend.err().unwrap_or_else(|| "ok".to_string()),
+        index: index.as_str(),
+    };
+
+    if ready {
+        (StatusCode::OK, Json(ReadinessResponse { status: "ready", checks }))
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(ReadinessResponse { status: "not ready", checks }))
+    }
+}
+
+async fn with_timeout(check: impl Future<Output = Result<(), String>>) -> Result<(), String> {
+    tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .map_err(|_| "timed out".to_string())?
+}
+
+async fn check_database(state: &ThreadSafeState) -> Result<(), String> {
+    use sqlx::Connection;
+
+    let mut conn = state.db_pool.acquire().await.map_err(|e| e.to_string())?;
+    conn.ping().await.map_err(|e| e.to_string())
+}
+
+// Uploads and archive items are written under the filestore, so it isn't enough for it to be readable.
+async fn check_filestore(root: &str) -> Result<(), String> {
+    let path = format!("{root}/.ready-{}", Uuid::new_v4().hyphenated());
+    tokio::fs::write(&path, b"").await.map_err(|e| e.to_string())?;
+    tokio::fs::remove_file(&path).await.map_err(|e| e.to_string())
+}
+
+// Reading a ghost shows that the backend is loaded and can parse runs. It runs on a blocking thread of its own
+// rather than in the simulation pool, so that a pool busy with uploads doesn't fail the probe.
+async fn check_backend(state: &ThreadSafeState) -> Result<(), String> {
+    if let Some((checked_at, result)) = LAST_BACKEND_CHECK.lock().unwrap().as_ref()
+        && checked_at.elapsed() < BACKEND_CHECK_TTL
+    {
+        return result.clone();
+    }
+
+    let result = probe_backend(state).await;
+    *LAST_BACKEND_CHECK.lock().unwrap() = Some((Instant::now(), result.clone()));
+    result
+}
+
+// The bundled fixture is used where the backend has one. Otherwise the newest stored ghost stands in, and with
+// neither the backend can't be checked, which doesn't count as ready.
+async fn probe_backend(state: &ThreadSafeState) -> Result<(), String> {
+    let (ghost, name) = match state.tt_backend.probe_ghost() {
+        Some(fixture) => (fixture.to_vec(), "fixture ghost".to_string()),
+        None => {
+            let tt = TimeTrialEntry::get_latest_valid(&state.db_pool, 1)
+                .await
+                .map_err(|e| e.to_string())?
+                .pop()
+                .ok_or("no ghost to check the backend with")?;
+            let ghost = read_tt_file_at(&get_tt_file_path(&state.config.filestore, tt.id))
+                .await
+                .map_err(|e| format!("failed to read ghost {}: {e}", tt.id))?;
+            (ghost, format!("ghost {}", tt.id))
+        }
+    };
+
+    let backend = state.tt_backend.clone();
+    tokio::task::spawn_blocking(move || backend.get_info(&ghost))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|_| ())
+        .map_err(|e| format!("failed to read {name}: {e}"))
+}
@@ -14,11 +14,11 @@ pub async fn create_account(State(state): State<ThreadSafeState>, Json(payload):
 
     let user = User::new_local_from_password(payload.username, payload.password, Some(false));
 
-    let pool = &state.lock().await.db_pool;
+    let pool = &state.db_pool;
 
     let username_exists = user.check_username_exists(pool).await;
     if let Err(e) = username_exists {
-        eprintln!("Database error on username lookup: {}", e);
+        tracing::error!(error = %e, "database error on username lookup");
         return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"status": "internal server error"})));
     } else if username_exists.unwrap_or(false) {
         return (StatusCode::CONFLICT, Json(serde_json::json!({"status": "username already exists"})));
@@ -32,7 +32,7 @@ pub async fn create_account(State(state): State<ThreadSafeState>, Json(payload):
 
     let insert_result = user.insert_or_update(pool).await;
     if let Err(e) = insert_result {
-        eprintln!("Database error on user insert: {}", e);
+        tracing::error!(error = %e, "database error on user insert");
         return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"status": "internal server error"})));
     } 
 
@@ -17,13 +17,13 @@ pub async fn login(
     State(state): State<ThreadSafeState>,
     Json(payload): Json<LoginPayload>,
 ) -> axum::response::Result<(StatusCode, Json<serde_json::Value>)> {
-    let pool = &state.lock().await.db_pool;
+    let pool = &state.db_pool;
 
     let user =
         User::get_by_username_and_local_password(pool, &payload.username, &payload.password).await;
 
     if let Err(e) = user {
-        eprintln!("Database error on username lookup: {}", e);
+        tracing::error!(error = %e, "database error on username lookup");
         return Ok((
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({"status": "internal server error"})),
@@ -0,0 +1,35 @@
+use axum::{
+    extract::State,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+
+use crate::{archive::ArchiveItemType, db::archive::archive_item::ArchiveItem, metrics::ItemTypeLabels, state::{IndexState, ThreadSafeState}};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Prometheus scrape endpoint. It isn't authenticated, so it's served on `metrics_port` rather than with the API.
+pub async fn metrics(State(state): State<ThreadSafeState>) -> impl IntoResponse {
+    let metrics = &state.metrics;
+
+    metrics.db_pool_connections.set(state.db_pool.size() as i64);
+    metrics.db_pool_idle_connections.set(state.db_pool.num_idle() as i64);
+    metrics.db_pool_max_connections.set(state.config.database.max_connections as i64);
+    metrics.index_regenerating.set((state.index_state.get() == IndexState::Regenerating) as i64);
+
+    // Leave the item counts as they were if the database is unavailable; the pool gauges already show it.
+    match ArchiveItem::count_by_type(&state.db_pool).await {
+        Ok(counts) => {
+            for t in ArchiveItemType::ALL {
+                let count = counts.get(&t.to_string()).copied().unwrap_or(0);
+                metrics.archive_items.get_or_create(&ItemTypeLabels { r#type: t.to_string() }).set(count);
+            }
+        }
+        Err(e) => tracing::warn!(error = %e, "failed to count archive items for metrics"),
+    }
+
+    match metrics.encode() {
+        Ok(body) => (StatusCode::OK, [(header::CONTENT_TYPE, CONTENT_TYPE)], body),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, [(header::CONTENT_TYPE, "text/plain")], "failed to encode metrics".to_string()),
+    }
+}
@@ -1,15 +1,20 @@
-use axum::{body::Body, extract::State, http::{Response, StatusCode}, response::{IntoResponse, Json}};
+use axum::{body::Body, http::{Response, StatusCode}, response::{IntoResponse, Json}};
 use serde::Serialize;
 use serde_json::Value;
 
-use crate::state::ThreadSafeState;
-
+pub mod account;
 pub mod archive;
+pub mod health;
 pub mod local_create_account;
 pub mod local_login;
+pub mod metrics;
 pub mod oauth2;
 pub mod tt;
+pub mod user;
 
-pub async fn root(State(_): State<ThreadSafeState>) -> Json<Value> {
-    Json(serde_json::json!({"status": "healthy"}))
-}
\ No newline at end of file
+/// Logs an unexpected database error and replies with a generic one. The request ID added to the reply
+/// links it to the log entry.
+pub fn db_error<E: std::fmt::Display>(e: E) -> (StatusCode, Json<Value>) {
+    tracing::error!(error = %e, "database error");
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"status": "internal database error"})))
+}
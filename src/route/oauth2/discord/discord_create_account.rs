@@ -43,9 +43,7 @@ pub async fn create_account(
             )
         )
     }
-
-    let lock = state.lock().await;
-    let pool = &lock.db_pool;
+    let pool = &state.db_pool;
 
     let discord_info = DiscordOauth2TokenMapping::get_from_session_token(pool, payload.temp_token)
         .await
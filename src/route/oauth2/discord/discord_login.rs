@@ -33,20 +33,18 @@ pub async fn login(
         ));
     }
 
-    let lock = state.lock().await;
-
     let discord_token = exchange_code_for_token(
-        &lock.req_client,
-        lock.config.discord.client_id,
-        &lock.config.discord.client_secret,
+        &state.req_client,
+        state.config.discord.client_id,
+        &state.config.discord.client_secret,
         payload.code,
         payload.redirect_uri,
     )
     .await?;
 
-    let id = get_user_id_from_token(&lock.req_client, &discord_token).await?;
+    let id = get_user_id_from_token(&state.req_client, &discord_token).await?;
 
-    let pool = &lock.db_pool;
+    let pool = &state.db_pool;
 
     // lookup id in Oauth2 table, if it exists issue token. If not, reject with 404 and
     // ask user to create an account.
@@ -0,0 +1,54 @@
+// Withdraws one of the authenticated user's time trials, along with its history and ghosts.
+
+use axum::{Json, extract::State, http::HeaderMap};
+use reqwest::StatusCode;
+use serde_json::json;
+use sqlx::types::Uuid;
+
+use crate::{
+    db::{token::UserToken, tt::tt_entry::TimeTrialEntry},
+    route::db_error,
+    state::ThreadSafeState,
+    tt::delete_tt_files,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DeleteTTRequest {
+    pub tt_id: String,
+}
+
+pub async fn delete_tt(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<DeleteTTRequest>,
+) -> axum::response::Result<(StatusCode, Json<serde_json::Value>)> {
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "missing or invalid Authorization header"}))))?;
+    let tt_id = Uuid::parse_str(&req.tt_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"status": "invalid tt_id"}))))?;
+
+    let pool = &state.db_pool;
+    let root = &state.config.filestore;
+
+    let user_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
+
+    // Someone else's time trial is reported as missing, so IDs can't be probed.
+    let tt = TimeTrialEntry::get(pool, tt_id)
+        .await
+        .map_err(db_error)?
+        .filter(|tt| tt.user_id == user_id)
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "time trial not found"}))))?;
+
+    let history_ids = TimeTrialEntry::delete(pool, tt.id, user_id, None)
+        .await
+        .map_err(db_error)?;
+    delete_tt_files(root, tt.id, &history_ids).await;
+
+    Ok((StatusCode::OK, Json(json!({"status": "time trial deleted"}))))
+}
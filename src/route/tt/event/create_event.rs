@@ -0,0 +1,77 @@
+// Creates a time trial event. Admins only.
+
+use axum::{Json, extract::State, http::HeaderMap};
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::{
+    archive::{ArchiveItemRef, ArchiveItemType},
+    db::{tt::event::TimeTrialEvent, user_role::ROLE_ADMIN},
+    route::{
+        db_error,
+        tt::{
+            event::{EventResponse, now_utc, parse_timestamp},
+            get_archived_item, require_role, resolve_item_ref,
+        },
+    },
+    state::ThreadSafeState,
+};
+
+pub const MAX_EVENT_NAME_LENGTH: usize = 100;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CreateEventRequest {
+    pub name: String,
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "stage_id" name
+    #[serde(alias = "stage_id")]
+    pub stage: ArchiveItemRef,
+    /// The cars runs may be set with; at least one
+    pub cars: Vec<ArchiveItemRef>,
+    /// RFC 3339
+    pub starts_at: String,
+    /// RFC 3339
+    pub ends_at: String,
+}
+
+pub async fn create_event(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<CreateEventRequest>,
+) -> axum::response::Result<(StatusCode, Json<EventResponse>)> {
+    let pool = &state.db_pool;
+
+    let user_id = require_role(pool, &headers, &[ROLE_ADMIN]).await?;
+
+    let name = req.name.trim();
+    if name.is_empty() || name.len() > MAX_EVENT_NAME_LENGTH {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": format!("name must be between 1 and {MAX_EVENT_NAME_LENGTH} characters")}))).into());
+    }
+    let starts_at = parse_timestamp(&req.starts_at, "starts_at")?;
+    let ends_at = parse_timestamp(&req.ends_at, "ends_at")?;
+    if ends_at <= starts_at {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "ends_at must be after starts_at"}))).into());
+    }
+    if ends_at <= now_utc() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "ends_at must be in the future"}))).into());
+    }
+    if req.cars.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "at least one car is required"}))).into());
+    }
+
+    let stage = resolve_item_ref(pool, req.stage, "stage_id").await?;
+    get_archived_item(pool, &stage, ArchiveItemType::Stage, "stage_id").await?;
+    let mut cars = Vec::with_capacity(req.cars.len());
+    for car in req.cars {
+        let car = resolve_item_ref(pool, car, "car").await?;
+        get_archived_item(pool, &car, ArchiveItemType::Car, "car").await?;
+        if !cars.contains(&car) {
+            cars.push(car);
+        }
+    }
+
+    let event = TimeTrialEvent::insert(pool, name, &stage, &cars, starts_at, ends_at, user_id)
+        .await
+        .map_err(db_error)?;
+
+    Ok((StatusCode::CREATED, Json(EventResponse::from_event(event, cars))))
+}
@@ -0,0 +1,113 @@
+// Standings of a time trial event. While the event runs, these are live; once it has ended, they are the frozen
+// results. The stage's all-time leaderboard is still served by /tt/leaderboard.
+
+use axum::{Json, extract::State};
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::{
+    archive::ArchiveItemKey,
+    db::tt::event::{EventStanding, TimeTrialEvent},
+    route::{
+        db_error,
+        tt::{
+            event::{EventResponse, now_utc},
+            leaderboard::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+        },
+    },
+    state::ThreadSafeState,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct EventLeaderboardRequest {
+    pub event_id: i32,
+    /// Zero-based
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct EventStandingResponse {
+    pub rank: i64,
+    pub username: String,
+    pub car: ArchiveItemKey,
+    pub ticks: i32,
+    pub tt_version: i32,
+    /// ISO 8601 format
+    pub set_at: String,
+    /// ID of the run in the owner's time trial history; None if its ghost was deleted since
+    pub history_id: Option<String>,
+}
+impl From<EventStanding> for EventStandingResponse {
+    fn from(standing: EventStanding) -> Self {
+        EventStandingResponse {
+            rank: standing.rank,
+            car: standing.car(),
+            username: standing.username,
+            ticks: standing.total_ticks,
+            tt_version: standing.tt_version,
+            set_at: standing.set_at.to_string(),
+            history_id: standing.history_id.map(|id| id.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct EventLeaderboardResponse {
+    pub event: EventResponse,
+    pub page: i64,
+    pub page_size: i64,
+    pub total: i64,
+    pub entries: Vec<EventStandingResponse>,
+}
+
+pub async fn event_leaderboard(
+    State(state): State<ThreadSafeState>,
+    Json(req): Json<EventLeaderboardRequest>,
+) -> axum::response::Result<(StatusCode, Json<EventLeaderboardResponse>)> {
+    let page = req.page.unwrap_or(0);
+    let page_size = req.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page < 0 {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "page must not be negative"}))).into());
+    }
+    if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": format!("page_size must be between 1 and {MAX_PAGE_SIZE}")}))).into());
+    }
+
+    let pool = &state.db_pool;
+
+    let get_event = async || {
+        TimeTrialEvent::get(pool, req.event_id)
+            .await
+            .map_err(db_error)?
+            .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "event not found"}))))
+    };
+    let mut event = get_event().await?;
+
+    // Events are frozen in the background, but there may be a short gap after the window closes.
+    if event.frozen_at.is_none() && event.ends_at <= now_utc() {
+        TimeTrialEvent::freeze_ended(pool)
+            .await
+            .map_err(db_error)?;
+        event = get_event().await?;
+    }
+
+    let (entries, total) = event
+        .standings(pool, page_size, page * page_size)
+        .await
+        .map_err(db_error)?;
+    let cars = TimeTrialEvent::get_cars(pool, event.id)
+        .await
+        .map_err(db_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(EventLeaderboardResponse {
+            event: EventResponse::from_event(event, cars),
+            page,
+            page_size,
+            total,
+            entries: entries.into_iter().map(EventStandingResponse::from).collect(),
+        }),
+    ))
+}
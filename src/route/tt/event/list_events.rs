@@ -0,0 +1,44 @@
+// Lists time trial events, most recent first; optionally only those on one stage.
+
+use axum::{Json, extract::State};
+use reqwest::StatusCode;
+
+use crate::{
+    archive::ArchiveItemRef,
+    db::tt::event::TimeTrialEvent,
+    route::{db_error, tt::{event::EventResponse, resolve_item_ref}},
+    state::ThreadSafeState,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ListEventsRequest {
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "stage_id" name
+    #[serde(alias = "stage_id")]
+    pub stage: Option<ArchiveItemRef>,
+}
+
+pub async fn list_events(
+    State(state): State<ThreadSafeState>,
+    Json(req): Json<ListEventsRequest>,
+) -> axum::response::Result<(StatusCode, Json<Vec<EventResponse>>)> {
+    let pool = &state.db_pool;
+
+    let stage = match req.stage {
+        Some(s) => Some(resolve_item_ref(pool, s, "stage_id").await?),
+        None => None,
+    };
+
+    let events = TimeTrialEvent::list(pool, stage.as_ref())
+        .await
+        .map_err(db_error)?;
+
+    let mut res = Vec::with_capacity(events.len());
+    for event in events {
+        let cars = TimeTrialEvent::get_cars(pool, event.id)
+            .await
+            .map_err(db_error)?;
+        res.push(EventResponse::from_event(event, cars));
+    }
+
+    Ok((StatusCode::OK, Json(res)))
+}
@@ -0,0 +1,73 @@
+use axum::Json;
+use reqwest::StatusCode;
+use serde_json::json;
+use sqlx::types::time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+use time::format_description::well_known::Rfc3339;
+
+use crate::{archive::ArchiveItemKey, db::tt::event::TimeTrialEvent};
+
+pub mod create_event;
+pub mod event_leaderboard;
+pub mod list_events;
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventStatus {
+    Upcoming,
+    Running,
+    /// The window has closed; the results are final once `frozen` is set
+    Ended,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct EventResponse {
+    pub id: i32,
+    pub name: String,
+    pub stage: ArchiveItemKey,
+    pub cars: Vec<ArchiveItemKey>,
+    /// ISO 8601 format, UTC
+    pub starts_at: String,
+    /// ISO 8601 format, UTC
+    pub ends_at: String,
+    pub status: EventStatus,
+    pub frozen: bool,
+    /// ISO 8601 format
+    pub created_at: String,
+}
+impl EventResponse {
+    pub fn from_event(event: TimeTrialEvent, cars: Vec<ArchiveItemKey>) -> Self {
+        let now = now_utc();
+        let status = if now < event.starts_at {
+            EventStatus::Upcoming
+        } else if now < event.ends_at {
+            EventStatus::Running
+        } else {
+            EventStatus::Ended
+        };
+        EventResponse {
+            id: event.id,
+            stage: event.stage(),
+            name: event.name,
+            cars,
+            starts_at: event.starts_at.to_string(),
+            ends_at: event.ends_at.to_string(),
+            status,
+            frozen: event.frozen_at.is_some(),
+            created_at: event.created_at.to_string(),
+        }
+    }
+}
+
+// Timestamps are stored without a time zone, in UTC like NOW() on the database server.
+pub fn now_utc() -> PrimitiveDateTime {
+    let now = OffsetDateTime::now_utc();
+    PrimitiveDateTime::new(now.date(), now.time())
+}
+
+/// Parses an RFC 3339 timestamp from a request into UTC. `field` is the name of the request field, for error messages.
+pub fn parse_timestamp(value: &str, field: &str) -> Result<PrimitiveDateTime, (StatusCode, Json<serde_json::Value>)> {
+    let parsed = OffsetDateTime::parse(value, &Rfc3339)
+        .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"status": format!("{field} must be an RFC 3339 timestamp")}))))?
+        .to_offset(UtcOffset::UTC);
+    Ok(PrimitiveDateTime::new(parsed.date(), parsed.time()))
+}
@@ -0,0 +1,168 @@
+// Fetches the ghosts of a range of ranks on a leaderboard in one request, e.g. to race against the top 10.
+// Returns multipart: "entries" is a JSON array with one BatchGhostEntry per rank in the range, and every included
+// ghost has its own file part, named in its entry. Ghosts are only included if the requesting user may see them
+// (see `tt::visibility`); the others are listed with the reason they were left out. Ghosts past MAX_BATCH_BYTES in
+// total are left out as well, and have to be fetched one by one with /tt/fetch.
+
+use axum::{Json, extract::State, http::HeaderMap, response::IntoResponse};
+use axum_extra::response::multiple::{MultipartForm, Part};
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::{
+    archive::{ArchiveItemKey, ArchiveItemRef},
+    db::{token::UserToken, tt::leaderboard::LeaderboardEntry},
+    route::{
+        db_error,
+        tt::{
+            fetch_tt::{accepts_zstd, load_ghost},
+            resolve_item_ref,
+        },
+    },
+    state::ThreadSafeState,
+    tt::{
+        backend::TimeTrialInfo,
+        get_tt_file_path,
+        visibility::{Ghost, GhostAccess, check_ghost_access},
+    },
+};
+
+pub const MAX_BATCH_SIZE: i64 = 25;
+/// Total size of the ghost files in one response. Uploads are limited to 10 MB, so the first ghost always fits.
+pub const MAX_BATCH_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FetchBatchRequest {
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "stage_id" name
+    #[serde(alias = "stage_id")]
+    pub stage: ArchiveItemRef,
+    // As above. If omitted, the ranks are those of the stage-wide leaderboard
+    #[serde(alias = "car_id")]
+    pub car: Option<ArchiveItemRef>,
+    /// First rank to include, starting at 1
+    pub from_rank: i64,
+    /// Last rank to include
+    pub to_rank: i64,
+    /// As for /tt/fetch
+    #[serde(default)]
+    pub accept_encodings: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchGhostStatus {
+    Included,
+    /// The owner's visibility setting doesn't include the requesting user
+    Restricted,
+    /// Entered in an event that hasn't ended
+    HiddenUntilEventEnds,
+    /// The file is missing from the filestore
+    Missing,
+    /// Would have made the response too large; fetch it with /tt/fetch instead
+    TooLarge,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct BatchGhostEntry {
+    pub rank: i64,
+    pub tt_id: String,
+    pub username: String,
+    pub car: ArchiveItemKey,
+    pub ticks: i32,
+    pub status: BatchGhostStatus,
+    /// Name of the part holding the ghost; only set if it's included
+    pub part: Option<String>,
+    /// Only set if the ghost is included
+    pub metadata: Option<TimeTrialInfo>,
+}
+
+pub async fn fetch_batch(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<FetchBatchRequest>,
+) -> axum::response::Result<axum::response::Response> {
+    if req.from_rank < 1 || req.to_rank < req.from_rank {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "ranks must start at 1, and to_rank must not be below from_rank"}))).into());
+    }
+    if req.to_rank - req.from_rank + 1 > MAX_BATCH_SIZE {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": format!("at most {MAX_BATCH_SIZE} ghosts can be fetched at once")}))).into());
+    }
+
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "missing or invalid Authorization header"}))))?;
+
+    let pool = &state.db_pool;
+    let root = &state.config.filestore;
+    let backend = &state.tt_backend;
+    let min_version = state.config.tt.min_leaderboard_version;
+
+    let viewer_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
+
+    let stage = resolve_item_ref(pool, req.stage, "stage_id").await?;
+    let car = match req.car {
+        Some(c) => Some(resolve_item_ref(pool, c, "car_id").await?),
+        None => None,
+    };
+
+    let page = LeaderboardEntry::page(pool, &stage, car.as_ref(), min_version, req.to_rank - req.from_rank + 1, req.from_rank - 1)
+        .await
+        .map_err(db_error)?;
+
+    let accept_zstd = accepts_zstd(&req.accept_encodings);
+    let mut entries = Vec::with_capacity(page.entries.len());
+    let mut file_parts = Vec::new();
+    let mut total_bytes = 0;
+    // Once a ghost doesn't fit, the rest are left out too rather than loaded just to find out if they'd fit.
+    let mut full = false;
+    for entry in page.entries {
+        let access = check_ghost_access(pool, viewer_id, Ghost::Best(entry.id), entry.user_id)
+            .await
+            .map_err(db_error)?;
+
+        let part_name = format!("ghost_{}", entry.rank);
+        let (status, metadata) = match access {
+            GhostAccess::Restricted => (BatchGhostStatus::Restricted, None),
+            GhostAccess::HiddenUntilEventEnds => (BatchGhostStatus::HiddenUntilEventEnds, None),
+            GhostAccess::Allowed if full => (BatchGhostStatus::TooLarge, None),
+            GhostAccess::Allowed => match load_ghost(&get_tt_file_path(root, entry.id), backend, &part_name, accept_zstd).await {
+                Ok(Some((_, _, size))) if total_bytes + size > MAX_BATCH_BYTES => {
+                    full = true;
+                    (BatchGhostStatus::TooLarge, None)
+                }
+                Ok(Some((metadata, part, size))) => {
+                    total_bytes += size;
+                    file_parts.push(part);
+                    (BatchGhostStatus::Included, Some(metadata))
+                }
+                Ok(None) => (BatchGhostStatus::Missing, None),
+                // One unreadable ghost shouldn't fail the whole batch.
+                Err(e) => {
+                    tracing::warn!(tt_id = %entry.id, error = %e, "failed to load ghost for a batch fetch");
+                    (BatchGhostStatus::Missing, None)
+                }
+            },
+        };
+
+        entries.push(BatchGhostEntry {
+            rank: entry.rank,
+            tt_id: entry.id.to_string(),
+            car: entry.car(),
+            username: entry.username,
+            ticks: entry.total_ticks,
+            part: metadata.is_some().then_some(part_name),
+            metadata,
+            status,
+        });
+    }
+
+    let mut parts = vec![Part::text("entries".to_owned(), &serde_json::to_string(&entries).unwrap())];
+    parts.extend(file_parts);
+
+    Ok(MultipartForm::with_parts(parts).into_response())
+}
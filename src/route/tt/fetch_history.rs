@@ -0,0 +1,89 @@
+// Fetch the ghost of one run from a time trial's history, by the entry ID listed in /tt/history. The response is
+// the same as for /tt/fetch, including the "accept_encodings" negotiation.
+// Requires a logged in user. The owner's visibility setting applies as for the best ghost, and a run entered in an
+// event that hasn't ended stays hidden, whether or not it's still the best.
+
+use axum::{Json, extract::State, http::HeaderMap, response::IntoResponse};
+use axum_extra::response::multiple::{MultipartForm, Part};
+use reqwest::StatusCode;
+use serde_json::json;
+use sqlx::types::Uuid;
+
+use crate::{
+    db::{token::UserToken, tt::{tt_entry::TimeTrialEntry, tt_history::TimeTrialHistoryEntry}},
+    route::{db_error, tt::fetch_tt::{accepts_zstd, load_ghost}},
+    state::ThreadSafeState,
+    tt::{
+        get_tt_file_path, get_tt_history_file_path,
+        visibility::{Ghost, GhostAccess, check_ghost_access},
+    },
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FetchHistoryRequest {
+    pub history_id: String,
+    /// As for /tt/fetch
+    #[serde(default)]
+    pub accept_encodings: Vec<String>,
+}
+
+pub async fn fetch_history(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<FetchHistoryRequest>,
+) -> axum::response::Result<axum::response::Response> {
+    let history_id = Uuid::parse_str(&req.history_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"status": "invalid history_id"}))))?;
+
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "missing or invalid Authorization header"}))))?;
+
+    let pool = &state.db_pool;
+    let root = &state.config.filestore;
+
+    let viewer_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
+
+    let not_found = || (StatusCode::NOT_FOUND, Json(json!({"status": "history entry not found"})));
+    let entry = TimeTrialHistoryEntry::get(pool, history_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(not_found)?;
+    let tt = TimeTrialEntry::get_by_history_id(pool, history_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(not_found)?;
+
+    let access = check_ghost_access(pool, viewer_id, Ghost::History(entry.id), tt.user_id)
+        .await
+        .map_err(db_error)?;
+    if access != GhostAccess::Allowed {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"status": access.message()}))).into());
+    }
+
+    // The best run's ghost is the time trial file; see `TimeTrialHistoryEntry`.
+    let path = if entry.is_best {
+        get_tt_file_path(root, tt.id)
+    } else {
+        get_tt_history_file_path(root, entry.id)
+    };
+    let (metadata, file_part, _) = load_ghost(&path, &state.tt_backend, "file", accepts_zstd(&req.accept_encodings))
+        .await
+        .map_err(|e| {
+            tracing::error!(%history_id, error = %e, "failed to load history ghost");
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"status": "failed to read ghost"})))
+        })?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "the ghost of this run is no longer stored"}))))?;
+
+    let parts = vec![
+        Part::text("metadata".to_owned(), &serde_json::to_string(&metadata).unwrap()),
+        file_part,
+    ];
+
+    Ok(MultipartForm::with_parts(parts).into_response())
+}
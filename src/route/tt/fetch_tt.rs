@@ -1,54 +1,122 @@
 // Fetch a TT by its UUID. Returns the TT file data, as well as the metadata, using multipart:
-// "metadata" based on a subset of GetTTInfoResult, and "file" containing the raw TT file bytes.
+// "metadata" containing the TimeTrialInfo, and "file" containing the raw TT file bytes.
+// Requires a logged in user, and only serves ghosts they may see; see `tt::visibility`.
+//
+// Ghosts are stored zstd-compressed. Clients that list "zstd" in `accept_encodings` get the compressed frame as
+// stored, with a content type of "application/zstd" and a file name of "timetrial.zst"; everyone else gets the raw
+// replay. Ghosts stored before compression was introduced are always sent raw.
+
+use std::sync::Arc;
 
 use axum::{Json, extract::State, http::HeaderMap, response::IntoResponse};
 use axum_extra::response::multiple::{MultipartForm, Part};
+use reqwest::StatusCode;
+use serde_json::json;
+use sqlx::types::Uuid;
+
+use crate::{
+    db::{token::UserToken, tt::tt_entry::TimeTrialEntry},
+    route::db_error,
+    tt::{
+        backend::{TimeTrialBackend, TimeTrialInfo},
+        get_tt_file_path, read_stored_tt_file_at,
+        storage::{StoredFormat, decode, split_header},
+        visibility::{Ghost, GhostAccess, check_ghost_access},
+    },
+};
 
-use crate::ffi::GetTTInfoArgs;
 
 #[derive(Debug, serde::Deserialize)]
 pub struct FetchTTRequest {
     pub tt_id: String,
+    /// Encodings the client can decode the file in, besides the raw replay
+    #[serde(default)]
+    pub accept_encodings: Vec<String>,
+}
+
+pub fn accepts_zstd(accept_encodings: &[String]) -> bool {
+    accept_encodings.iter().any(|e| e == "zstd")
 }
 
-#[derive(serde::Serialize)]
-pub struct FetchTTMetadata {
-    pub checkpoint_count: i32,
-    pub tick_count: i32,
-    pub replay_version: i32,
-    pub backend_version: i32,
+/// Reads the ghost stored at `path` and builds its file part under `part_name`, compressed if the client accepts it.
+/// Also returns the size of the file in the part. Returns None if the file is missing.
+pub async fn load_ghost(
+    path: &str,
+    backend: &Arc<dyn TimeTrialBackend>,
+    part_name: &str,
+    accept_zstd: bool,
+) -> Result<Option<(TimeTrialInfo, Part, usize)>, String> {
+    let stored = match read_stored_tt_file_at(path).await {
+        Ok(stored) => stored,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read TT file: {}", e)),
+    };
+
+    // Decompressing and parsing a replay of several MB takes long enough that it shouldn't run on the async runtime.
+    let backend = backend.clone();
+    let part_name = part_name.to_owned();
+    let loaded = tokio::task::spawn_blocking(move || {
+        let (format, payload) = split_header(&stored).map_err(|e| format!("Failed to read TT file: {}", e))?;
+        let tt_bytes = decode(&stored).map_err(|e| format!("Failed to read TT file: {}", e))?;
+
+        let metadata = backend.get_info(&tt_bytes).map_err(|e| format!("Failed to read TT info: {}", e))?;
+
+        let (part, size) = if format == StoredFormat::Zstd && accept_zstd {
+            (Part::raw_part(&part_name, "application/zstd", payload.to_vec(), Some("timetrial.zst")).unwrap(), payload.len())
+        } else {
+            let size = tt_bytes.len();
+            (Part::file(&part_name, "timetrial", tt_bytes), size)
+        };
+        Ok::<_, String>((metadata, part, size))
+    })
+    .await
+    .map_err(|e| format!("Failed to read TT file: {}", e))??;
+    Ok(Some(loaded))
 }
 
-// TODO: restrict to logged in users; for now no real need
 pub async fn fetch_tt(State(state): State<crate::state::ThreadSafeState>, headers: HeaderMap, Json(request): Json<FetchTTRequest>) -> axum::response::Result<axum::response::Response> {
-    let tt_uuid = sqlx::types::Uuid::parse_str(&request.tt_id)
-        .map_err(|_| axum::response::Response::builder().status(400).body("Invalid TT ID".to_owned()).unwrap())?;
+    let tt_uuid = Uuid::parse_str(&request.tt_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"status": "invalid tt_id"}))))?;
 
-    let tt_bytes = crate::tt::read_tt_file(&state, tt_uuid).await
-        .map_err(|_| axum::response::Response::builder().status(404).body("TT not found".to_owned()).unwrap())?;
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "missing or invalid Authorization header"}))))?;
 
-    let args = GetTTInfoArgs {
-        time_trial_data: tt_bytes.as_ptr(),
-        time_trial_data_length: tt_bytes.len() as i32,
-    };
+    let pool = &state.db_pool;
+    let root = &state.config.filestore;
+    let backend = &state.tt_backend;
 
-    let info = unsafe { crate::ffi::nfmw_get_tt_info(&args as *const _) };
+    let viewer_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
 
-    if info.has_error {
-        return Err(axum::response::Response::builder().status(500).body(format!("Failed to read TT info: {}", String::from_utf8_lossy(&info.exception.message))).unwrap().into());
+    let tt = TimeTrialEntry::get(pool, tt_uuid)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "time trial not found"}))))?;
+
+    let access = check_ghost_access(pool, viewer_id, Ghost::Best(tt.id), tt.user_id)
+        .await
+        .map_err(db_error)?;
+    if access != GhostAccess::Allowed {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"status": access.message()}))).into());
     }
 
-    let metadata = FetchTTMetadata {
-        checkpoint_count: info.checkpoint_count,
-        tick_count: info.tick_count,
-        replay_version: info.replay_version,
-        backend_version: info.backend_version,
-    };
+    let (metadata, file_part, _) = load_ghost(&get_tt_file_path(root, tt.id), backend, "file", accepts_zstd(&request.accept_encodings))
+        .await
+        .map_err(|e| {
+            tracing::error!(tt_id = %tt.id, error = %e, "failed to load ghost");
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"status": "failed to read ghost"})))
+        })?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "time trial not found"}))))?;
 
     let multipart_parts = vec![
         Part::text("metadata".to_owned(), &serde_json::to_string(&metadata).unwrap()),
-        Part::file("file", "timetrial", tt_bytes)
+        file_part
     ];
 
     Ok(MultipartForm::with_parts(multipart_parts).into_response())
-}
\ No newline at end of file
+}
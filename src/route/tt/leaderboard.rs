@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use axum::{Json, extract::State, http::HeaderMap};
+use reqwest::StatusCode;
+use serde_json::json;
+use sqlx::types::Uuid;
+
+use crate::{
+    archive::{ArchiveItemKey, ArchiveItemRef},
+    db::{
+        archive::archive_item::ArchiveItem,
+        tt::leaderboard::{CarRecord, LeaderboardEntry, LeaderboardPage},
+    },
+    route::{db_error, tt::{optional_user, resolve_item_ref}},
+    state::ThreadSafeState,
+};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct LeaderboardRequest {
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "stage_id" name
+    #[serde(alias = "stage_id")]
+    pub stage: ArchiveItemRef,
+    // As above. If omitted, all cars are ranked together and each user is ranked by their fastest car
+    #[serde(alias = "car_id")]
+    pub car: Option<ArchiveItemRef>,
+    /// Zero-based
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct LeaderboardEntryResponse {
+    pub rank: i64,
+    pub id: String,
+    pub ticks: i32,
+    pub username: String,
+    pub car: ArchiveItemKey,
+    pub stage: ArchiveItemKey,
+    /// Legacy uuid of the car, for older clients. None if the car doesn't have one.
+    pub car_id: Option<String>,
+    /// Legacy uuid of the stage, for older clients. None if the stage doesn't have one.
+    pub stage_id: Option<String>,
+    pub tt_version: i32,
+    /// ISO 8601 format
+    pub created_at: String,
+}
+impl LeaderboardEntryResponse {
+    pub fn from_leaderboard_entry(entry: LeaderboardEntry, legacy_ids: &HashMap<ArchiveItemKey, Uuid>) -> Self {
+        let car = entry.car();
+        let stage = entry.stage();
+        LeaderboardEntryResponse {
+            rank: entry.rank,
+            id: entry.id.to_string(),
+            ticks: entry.total_ticks,
+            username: entry.username,
+            car_id: legacy_ids.get(&car).map(|id| id.to_string()),
+            stage_id: legacy_ids.get(&stage).map(|id| id.to_string()),
+            car,
+            stage,
+            tt_version: entry.tt_version,
+            created_at: entry.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CarRecordResponse {
+    pub car: ArchiveItemKey,
+    /// Legacy uuid of the car, for older clients. None if the car doesn't have one.
+    pub car_id: Option<String>,
+    /// Number of ranked time trials on this stage with this car
+    pub entries: i64,
+    pub id: String,
+    pub ticks: i32,
+    pub username: String,
+    pub tt_version: i32,
+    /// ISO 8601 format
+    pub created_at: String,
+}
+impl CarRecordResponse {
+    pub fn from_car_record(record: CarRecord, legacy_ids: &HashMap<ArchiveItemKey, Uuid>) -> Self {
+        let car = record.car();
+        CarRecordResponse {
+            car_id: legacy_ids.get(&car).map(|id| id.to_string()),
+            car,
+            entries: record.entry_count,
+            id: record.id.to_string(),
+            ticks: record.total_ticks,
+            username: record.username,
+            tt_version: record.tt_version,
+            created_at: record.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct LeaderboardResponse {
+    pub page: i64,
+    pub page_size: i64,
+    /// Number of ranked entries on the whole board
+    pub total: i64,
+    pub entries: Vec<LeaderboardEntryResponse>,
+    /// The requesting user's best entry on this board, if they are logged in and have one.
+    /// Present even if it falls outside the requested page.
+    pub own_entry: Option<LeaderboardEntryResponse>,
+    /// Only for stage-wide boards: the record for each car driven on the stage, fastest first.
+    pub cars: Option<Vec<CarRecordResponse>>,
+}
+
+// The Authorization header is optional here; it is only used to look up the requesting user's own rank.
+pub async fn leaderboard(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<LeaderboardRequest>,
+) -> axum::response::Result<(StatusCode, Json<LeaderboardResponse>)> {
+    let page = req.page.unwrap_or(0);
+    let page_size = req.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page < 0 {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "page must not be negative"}))).into());
+    }
+    if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": format!("page_size must be between 1 and {MAX_PAGE_SIZE}")}))).into());
+    }
+    let pool = &state.db_pool;
+    let min_version = state.config.tt.min_leaderboard_version;
+
+    let stage = resolve_item_ref(pool, req.stage, "stage_id").await?;
+    let car = match req.car {
+        Some(c) => Some(resolve_item_ref(pool, c, "car_id").await?),
+        None => None,
+    };
+
+    let user_id = optional_user(pool, &headers).await?;
+
+    let LeaderboardPage { entries, total } = LeaderboardEntry::page(pool, &stage, car.as_ref(), min_version, page_size, page * page_size)
+        .await
+        .map_err(db_error)?;
+
+    let own_entry = if let Some(uid) = user_id {
+        LeaderboardEntry::user_entry(pool, &stage, car.as_ref(), min_version, uid)
+            .await
+            .map_err(db_error)?
+    } else {
+        None
+    };
+
+    let records = if car.is_none() {
+        Some(CarRecord::for_stage(pool, &stage, min_version)
+            .await
+            .map_err(db_error)?)
+    } else {
+        None
+    };
+
+    let mut keys = vec![stage];
+    keys.extend(entries.iter().chain(own_entry.iter()).map(|e| e.car()));
+    keys.extend(records.iter().flatten().map(|r| r.car()));
+    let legacy_ids = ArchiveItem::get_legacy_ids(pool, &keys)
+        .await
+        .map_err(db_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(LeaderboardResponse {
+            page,
+            page_size,
+            total,
+            entries: entries.into_iter().map(|e| LeaderboardEntryResponse::from_leaderboard_entry(e, &legacy_ids)).collect(),
+            own_entry: own_entry.map(|e| LeaderboardEntryResponse::from_leaderboard_entry(e, &legacy_ids)),
+            cars: records.map(|r| r.into_iter().map(|r| CarRecordResponse::from_car_record(r, &legacy_ids)).collect()),
+        }),
+    ))
+}
\ No newline at end of file
@@ -1,3 +1,99 @@
+use axum::{Json, http::HeaderMap};
+use reqwest::StatusCode;
+use serde_json::json;
+use sqlx::{PgPool, types::Uuid};
+
+use crate::{
+    archive::{ArchiveItemKey, ArchiveItemRef, ArchiveItemType},
+    db::{archive::archive_item::ArchiveItem, token::UserToken, user_role::user_has_role},
+    route::db_error,
+    tt::pool::SimulationPoolError,
+};
+
 pub mod upload_tt;
 pub mod search_tt;
-pub mod fetch_tt;
\ No newline at end of file
+pub mod fetch_tt;
+pub mod fetch_batch;
+pub mod fetch_history;
+pub mod leaderboard;
+pub mod tt_history;
+pub mod revalidation_report;
+pub mod delete_tt;
+pub mod event;
+pub mod moderation;
+
+/// Resolves a car or stage reference from a request to its (author, name) key. Legacy UUIDs are looked up;
+/// keys are passed through as-is. `field` is the name of the request field, for error messages.
+pub async fn resolve_item_ref(pool: &PgPool, item: ArchiveItemRef, field: &str) -> Result<ArchiveItemKey, (StatusCode, Json<serde_json::Value>)> {
+    match item {
+        ArchiveItemRef::Key(key) => Ok(key),
+        ArchiveItemRef::LegacyId(id) => {
+            let uuid = Uuid::parse_str(&id)
+                .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"status": format!("invalid {field} uuid")}))))?;
+            ArchiveItem::get_key_by_legacy_id(pool, uuid)
+                .await
+                .map_err(db_error)?
+                .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": format!("no item with that {field}")}))))
+        }
+    }
+}
+/// Looks up the archived item behind a resolved key, making sure it exists and is of the expected type.
+pub async fn get_archived_item(pool: &PgPool, key: &ArchiveItemKey, r#type: ArchiveItemType, field: &str) -> Result<ArchiveItem, (StatusCode, Json<serde_json::Value>)> {
+    let item = ArchiveItem::get_by_key(pool, key)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": format!("no item with that {field}")}))))?;
+
+    if item.r#type != r#type.to_string() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": format!("{field} is not a {}", r#type.to_string())}))));
+    }
+    Ok(item)
+}
+
+/// Maps a simulation pool failure to the response for the client. A full queue is the client's cue to back off
+/// and retry; a timeout means the server itself is overloaded.
+pub fn sim_pool_error_response(e: SimulationPoolError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match e {
+        SimulationPoolError::QueueFull => StatusCode::TOO_MANY_REQUESTS,
+        SimulationPoolError::TimedOut => StatusCode::SERVICE_UNAVAILABLE,
+        SimulationPoolError::Failed => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, Json(json!({"status": e.to_string()})))
+}
+
+/// Authenticates the request if it carries an Authorization header, for endpoints that are public but show the
+/// caller their own hidden entries. Returns None for anonymous requests; a bad token is still rejected.
+pub async fn optional_user(pool: &PgPool, headers: &HeaderMap) -> Result<Option<i32>, (StatusCode, Json<serde_json::Value>)> {
+    let Some(authorization) = headers.get("Authorization") else {
+        return Ok(None);
+    };
+    let authorization = authorization.to_str()
+        .map_err(|_| (StatusCode::UNAUTHORIZED, Json(json!({"status": "missing or invalid Authorization header"}))))?;
+    let user = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?;
+    Ok(Some(user.user_id))
+}
+
+/// Authenticates the request and checks that the user has one of `roles`. Returns the user's ID.
+pub async fn require_role(pool: &PgPool, headers: &HeaderMap, roles: &[&str]) -> Result<i32, (StatusCode, Json<serde_json::Value>)> {
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "missing or invalid Authorization header"}))))?;
+    let user_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
+
+    let allowed = user_has_role(pool, user_id, roles)
+        .await
+        .map_err(db_error)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, Json(json!({"status": "insufficient permissions"}))));
+    }
+
+    Ok(user_id)
+}
@@ -0,0 +1,103 @@
+// The moderation queue: flagged runs awaiting review, oldest first.
+
+use axum::{Json, extract::State, http::HeaderMap};
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::{
+    archive::ArchiveItemKey,
+    db::{tt::flag::{PendingFlag, TimeTrialFlag}, user_role::MODERATOR_ROLES},
+    route::{
+        db_error,
+        tt::{
+            leaderboard::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+            require_role,
+        },
+    },
+    state::ThreadSafeState,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FlagQueueRequest {
+    /// Zero-based
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct FlagResponse {
+    pub flag_id: i32,
+    pub tt_id: String,
+    /// ID of the flagged run in the time trial's history; its ghost is kept until the flag is reviewed
+    pub history_id: Option<String>,
+    pub username: String,
+    pub car: ArchiveItemKey,
+    pub stage: ArchiveItemKey,
+    pub ticks: i32,
+    pub reasons: Vec<String>,
+    /// Whether the run is the user's best, i.e. it was their first run on the car and stage and their time trial is
+    /// being kept off leaderboards
+    pub is_best: bool,
+    /// Whether approving the run would make it the user's best
+    pub improves: bool,
+    /// ISO 8601 format
+    pub flagged_at: String,
+}
+impl From<PendingFlag> for FlagResponse {
+    fn from(flag: PendingFlag) -> Self {
+        FlagResponse {
+            flag_id: flag.id,
+            tt_id: flag.time_trial_id.to_string(),
+            history_id: flag.history_id.map(|id| id.to_string()),
+            car: flag.car(),
+            stage: flag.stage(),
+            username: flag.username,
+            ticks: flag.total_ticks,
+            reasons: flag.reasons,
+            is_best: flag.is_best,
+            improves: flag.improves,
+            flagged_at: flag.flagged_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct FlagQueueResponse {
+    pub page: i64,
+    pub page_size: i64,
+    pub total: i64,
+    pub flags: Vec<FlagResponse>,
+}
+
+pub async fn flag_queue(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<FlagQueueRequest>,
+) -> axum::response::Result<(StatusCode, Json<FlagQueueResponse>)> {
+    let page = req.page.unwrap_or(0);
+    let page_size = req.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page < 0 {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "page must not be negative"}))).into());
+    }
+    if !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": format!("page_size must be between 1 and {MAX_PAGE_SIZE}")}))).into());
+    }
+
+    let pool = &state.db_pool;
+
+    require_role(pool, &headers, MODERATOR_ROLES).await?;
+
+    let (flags, total) = TimeTrialFlag::get_pending(pool, page_size, page * page_size)
+        .await
+        .map_err(db_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(FlagQueueResponse {
+            page,
+            page_size,
+            total,
+            flags: flags.into_iter().map(FlagResponse::from).collect(),
+        }),
+    ))
+}
@@ -0,0 +1,5 @@
+// Review of time trials flagged by the plausibility checks, and removal of cheated ones. Moderators and admins only.
+
+pub mod flag_queue;
+pub mod remove_tt;
+pub mod review_flag;
@@ -0,0 +1,49 @@
+// Removes a time trial that breaks the rules, along with its history and ghosts. The reason is kept in tt_removals.
+
+use axum::{Json, extract::State, http::HeaderMap};
+use reqwest::StatusCode;
+use serde_json::json;
+use sqlx::types::Uuid;
+
+use crate::{
+    db::{tt::tt_entry::TimeTrialEntry, user_role::MODERATOR_ROLES},
+    route::{db_error, tt::require_role},
+    state::ThreadSafeState,
+    tt::delete_tt_files,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RemoveTTRequest {
+    pub tt_id: String,
+    pub reason: String,
+}
+
+pub async fn remove_tt(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<RemoveTTRequest>,
+) -> axum::response::Result<(StatusCode, Json<serde_json::Value>)> {
+    let tt_id = Uuid::parse_str(&req.tt_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"status": "invalid tt_id"}))))?;
+    let reason = req.reason.trim();
+    if reason.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "a reason is required"}))).into());
+    }
+
+    let pool = &state.db_pool;
+    let root = &state.config.filestore;
+
+    let moderator_id = require_role(pool, &headers, MODERATOR_ROLES).await?;
+
+    let tt = TimeTrialEntry::get(pool, tt_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "time trial not found"}))))?;
+
+    let history_ids = TimeTrialEntry::delete(pool, tt.id, moderator_id, Some(reason))
+        .await
+        .map_err(db_error)?;
+    delete_tt_files(root, tt.id, &history_ids).await;
+
+    Ok((StatusCode::OK, Json(json!({"status": "time trial removed"}))))
+}
@@ -0,0 +1,134 @@
+// Approves or rejects a flagged run. An approved run becomes the user's best if it beats their current one (or that
+// was invalidated since), and is entered into the events it was set during. A flagged first run already is the
+// user's best, and approving it just puts their time trial on leaderboards.
+// Rejecting a first run invalidates the time trial it created, which keeps it off leaderboards until the user uploads
+// a run that passes. Any other rejected run was never the user's best, so it's only deleted, ghost and all.
+
+use axum::{Json, extract::State, http::HeaderMap};
+use reqwest::StatusCode;
+use serde_json::json;
+use tracing::error;
+
+use crate::{
+    db::{
+        tt::{event::TimeTrialEvent, flag::TimeTrialFlag, tt_entry::TimeTrialEntry, tt_history::TimeTrialHistoryEntry},
+        user_role::MODERATOR_ROLES,
+    },
+    route::{db_error, tt::require_role},
+    state::ThreadSafeState,
+    tt::{delete_tt_history_files, promote_tt_history_file},
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ReviewFlagRequest {
+    pub flag_id: i32,
+    pub approve: bool,
+    /// Shown as the reason a rejected time trial was invalidated
+    pub note: Option<String>,
+}
+
+pub async fn review_flag(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<ReviewFlagRequest>,
+) -> axum::response::Result<(StatusCode, Json<serde_json::Value>)> {
+    let pool = &state.db_pool;
+
+    let moderator_id = require_role(pool, &headers, MODERATOR_ROLES).await?;
+
+    let flag = TimeTrialFlag::get(pool, req.flag_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "flag not found"}))))?;
+    if flag.reviewed_at.is_some() {
+        return Err((StatusCode::CONFLICT, Json(json!({"status": "flag has already been reviewed"}))).into());
+    }
+
+    let note = req.note.as_deref().map(str::trim).filter(|n| !n.is_empty());
+
+    let tt = TimeTrialEntry::get(pool, flag.time_trial_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "time trial not found"}))))?;
+    let run = match flag.history_id {
+        Some(id) => TimeTrialHistoryEntry::get(pool, id)
+            .await
+            .map_err(db_error)?,
+        None => None,
+    };
+
+    let mut tx = pool.begin().await.map_err(db_error)?;
+    // Guards against two moderators reviewing the same flag at once
+    let reviewed = TimeTrialFlag::review(&mut *tx, flag.id, moderator_id, req.approve, note)
+        .await
+        .map_err(db_error)?;
+    if !reviewed {
+        return Err((StatusCode::CONFLICT, Json(json!({"status": "flag has already been reviewed"}))).into());
+    }
+
+    let mut events = Vec::new();
+    // Set when the run replaces the user's best, to the history entry of the best it replaced, if any
+    let mut promoted = None;
+    let mut rejected_run = None;
+    if let Some(run) = &run {
+        if req.approve {
+            if run.is_best {
+                TimeTrialEntry::set_flagged(&mut *tx, tt.id, false)
+                    .await
+                    .map_err(db_error)?;
+            } else if let (Some(backend_version), Some(checkpoint_ticks)) = (flag.backend_version, &flag.checkpoint_ticks) {
+                // Flags from before the run's details were kept can't be promoted; the user has to upload it again.
+                // The update only goes through if the run still beats the user's best, which may have improved
+                // since the run was flagged.
+                let replaced = TimeTrialEntry::update(&mut *tx, tt.id, run.tt_version, backend_version, run.total_ticks)
+                    .await
+                    .map_err(db_error)?;
+                if replaced {
+                    let previous_best = TimeTrialHistoryEntry::get_best(&mut *tx, tt.id)
+                        .await
+                        .map_err(db_error)?;
+                    TimeTrialHistoryEntry::set_best(&mut tx, tt.id, run.id)
+                        .await
+                        .map_err(db_error)?;
+                    TimeTrialEntry::set_splits(&mut *tx, tt.id, checkpoint_ticks)
+                        .await
+                        .map_err(db_error)?;
+                    promoted = Some(previous_best.map(|h| h.id));
+                }
+            }
+            events = TimeTrialEvent::record_run(&mut *tx, tt.user_id, &tt.car(), &tt.stage(), run)
+                .await
+                .map_err(db_error)?;
+        } else if run.is_best {
+            let reason = match note {
+                Some(note) => format!("rejected in review: {note}"),
+                None => "rejected in review".to_string(),
+            };
+            TimeTrialEntry::invalidate(&mut *tx, tt.id, &reason, None)
+                .await
+                .map_err(db_error)?;
+        } else {
+            TimeTrialHistoryEntry::delete(&mut *tx, run.id)
+                .await
+                .map_err(db_error)?;
+            rejected_run = Some(run.id);
+        }
+    }
+
+    tx.commit().await.map_err(db_error)?;
+
+    // The review is recorded by now, so a failed file operation is only logged.
+    if let (Some(run), Some(previous_best)) = (&run, promoted)
+        && let Err(e) = promote_tt_history_file(&state.config.filestore, tt.id, run.id, previous_best).await
+    {
+        error!(tt_id = %tt.id, history_id = %run.id, error = %e, "failed to move approved TT file into place");
+    }
+    if let Some(id) = rejected_run {
+        delete_tt_history_files(&state.config.filestore, &[id]).await;
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"status": if req.approve { "approved" } else { "rejected" }, "events": events, "personal_best": promoted.is_some()})),
+    ))
+}
@@ -0,0 +1,42 @@
+// Report of a re-validation run: which time trials stopped reproducing after a backend update, grouped by
+// user, car and stage. Defaults to the latest run. Only moderators can see it, since it lists other users' runs
+// and why they failed.
+
+use axum::{Json, extract::State, http::HeaderMap};
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::{
+    db::{tt::revalidation::RevalidationRun, user_role::MODERATOR_ROLES},
+    route::{db_error, tt::require_role},
+    state::ThreadSafeState,
+    tt::revalidate::RevalidationReport,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RevalidationReportRequest {
+    pub run_id: Option<i32>,
+}
+
+pub async fn revalidation_report(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<RevalidationReportRequest>,
+) -> axum::response::Result<(StatusCode, Json<RevalidationReport>)> {
+    let pool = state.db_pool.clone();
+
+    require_role(&pool, &headers, MODERATOR_ROLES).await?;
+
+    let run = match req.run_id {
+        Some(id) => RevalidationRun::get(&pool, id).await,
+        None => RevalidationRun::get_latest(&pool).await,
+    }
+    .map_err(db_error)?
+    .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "no such revalidation run"}))))?;
+
+    let report = RevalidationReport::build(&pool, run)
+        .await
+        .map_err(db_error)?;
+
+    Ok((StatusCode::OK, Json(report)))
+}
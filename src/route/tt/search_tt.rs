@@ -1,17 +1,40 @@
+use std::collections::HashMap;
+
 use axum::{Json, extract::State, http::HeaderMap};
 use reqwest::StatusCode;
 use serde_json::{json, to_string};
 use sqlx::types::Uuid;
 
-use crate::{db::{tt::tt_entry::TimeTrialEntry, user::User}, state::ThreadSafeState};
+use crate::{
+    archive::{ArchiveItemKey, ArchiveItemRef},
+    db::{archive::archive_item::ArchiveItem, tt::tt_entry::{TimeTrialEntry, TimeTrialQuery, TimeTrialSort}, user::User},
+    route::{db_error, tt::{optional_user, resolve_item_ref}},
+    state::ThreadSafeState,
+};
 
 #[derive(Debug, serde::Deserialize)]
 pub struct SearchTTRequest {
     pub username: Option<String>,
-    // uuid to be parsed
-    pub car_id: Option<String>,
-    // uuid to be parsed
-    pub stage_id: Option<String>,
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "car_id" name
+    #[serde(alias = "car_id")]
+    pub car: Option<ArchiveItemRef>,
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "stage_id" name
+    #[serde(alias = "stage_id")]
+    pub stage: Option<ArchiveItemRef>,
+    /// "fastest" (default) or "newest"
+    #[serde(default)]
+    pub sort: TimeTrialSort,
+    /// 0-indexed
+    #[serde(default)]
+    pub page: u32,
+    #[serde(default = "default_page_size")]
+    pub page_size: u32,
+}
+
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+fn default_page_size() -> u32 {
+    MAX_PAGE_SIZE
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -19,48 +42,62 @@ pub struct SearchTTResponse {
     pub id: String,
     pub ticks: i32,
     pub username: String,
-    pub car_id: String,
-    pub stage_id: String,
+    pub car: ArchiveItemKey,
+    pub stage: ArchiveItemKey,
+    /// Legacy uuid of the car, for older clients. None if the car doesn't have one.
+    pub car_id: Option<String>,
+    /// Legacy uuid of the stage, for older clients. None if the stage doesn't have one.
+    pub stage_id: Option<String>,
     /// ISO 8601 format
     pub created_at: String,
+    /// Whether the run failed re-validation. Only the owner is shown such runs.
+    pub invalidated: bool,
 }
 impl SearchTTResponse {
-    pub fn from_time_trial_entry(entry: TimeTrialEntry, username: String) -> Self {
+    pub fn from_time_trial_entry(entry: TimeTrialEntry, username: String, legacy_ids: &HashMap<ArchiveItemKey, Uuid>) -> Self {
+        let car = entry.car();
+        let stage = entry.stage();
         SearchTTResponse {
             id: entry.id.to_string(),
             ticks: entry.total_ticks,
             username,
-            car_id: entry.car_id.to_string(),
-            stage_id: entry.stage_id.to_string(),
-            created_at: entry.created_at.map_or_else(|| String::new(), |dt| dt.to_string()),
+            car_id: legacy_ids.get(&car).map(|id| id.to_string()),
+            stage_id: legacy_ids.get(&stage).map(|id| id.to_string()),
+            car,
+            stage,
+            created_at: entry.created_at.map_or_else(String::new, |dt| dt.to_string()),
+            invalidated: entry.invalidated_at.is_some(),
         }
     }
 }
 
 fn validate_search_tt_request(req: &SearchTTRequest) -> Result<(), String> {
-    if req.username.is_none() && req.car_id.is_none() && req.stage_id.is_none() {
+    if req.username.is_none() && req.car.is_none() && req.stage.is_none() {
         return Err("At least one search parameter must be provided".to_string());
     }
+    if req.page_size == 0 || req.page_size > MAX_PAGE_SIZE {
+        return Err(format!("page_size must be between 1 and {}", MAX_PAGE_SIZE));
+    }
     Ok(())
 }
 
-// TODO: sort by fastest first; for now just return in whatever order the database gives us
-// TODO: limit number of results returned and add pagination; since we aren't storing many things yet it's not an issue
 pub async fn search_tt(State(state): State<ThreadSafeState>, headers: HeaderMap, axum::Json(req): axum::Json<SearchTTRequest>) -> axum::response::Result<(StatusCode, axum::Json<Vec<SearchTTResponse>>)> {
     validate_search_tt_request(&req).map_err(|e| (axum::http::StatusCode::BAD_REQUEST, axum::Json(json!({"status": e}))))?;
 
-    let pool= &state.lock().await.db_pool;
-    let car_uuid = req.car_id.as_ref()
-        .map(|s| Uuid::parse_str(s)).transpose()
-        .map_err(|_| (axum::http::StatusCode::BAD_REQUEST, axum::Json(json!({"status": "invalid car_id uuid"}))))?;
-    let stage_uuid = req.stage_id.as_ref()
-        .map(|s| Uuid::parse_str(s)).transpose()
-        .map_err(|_| (axum::http::StatusCode::BAD_REQUEST, axum::Json(json!({"status": "invalid stage_id uuid"}))))?;
+    let pool= &state.db_pool;
+    let car = match req.car {
+        Some(c) => Some(resolve_item_ref(pool, c, "car_id").await?),
+        None => None,
+    };
+    let stage = match req.stage {
+        Some(s) => Some(resolve_item_ref(pool, s, "stage_id").await?),
+        None => None,
+    };
 
     let user_id = if let Some(username) = req.username {
         let user = User::get_id_from_username(pool, &username)
             .await
-            .map_err(|_| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, axum::Json(json!({"status": "internal database error"}))))?;
+            .map_err(db_error)?;
         
         if let Some(u) = user {
             Some(u)
@@ -71,18 +108,30 @@ pub async fn search_tt(State(state): State<ThreadSafeState>, headers: HeaderMap,
         None
     };
 
-    let raw_tts = TimeTrialEntry::filter(pool, user_id, car_uuid, stage_uuid)
+    let viewer_id = optional_user(pool, &headers).await?;
+
+    let query = TimeTrialQuery {
+        user_id,
+        viewer_id,
+        car: car.as_ref(),
+        stage: stage.as_ref(),
+        sort: req.sort,
+        limit: req.page_size as i64,
+        offset: req.page as i64 * req.page_size as i64,
+    };
+    let results = TimeTrialEntry::search(pool, &query)
         .await
-        .map_err(|_| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, axum::Json(json!({"status": "internal database error"}))))?;
+        .map_err(db_error)?;
+
+    let keys = results.iter().flat_map(|r| [r.entry.car(), r.entry.stage()]).collect::<Vec<_>>();
+    let legacy_ids = ArchiveItem::get_legacy_ids(pool, &keys)
+        .await
+        .map_err(db_error)?;
+
+    let tts = results
+        .into_iter()
+        .map(|r| SearchTTResponse::from_time_trial_entry(r.entry, r.username, &legacy_ids))
+        .collect::<Vec<_>>();
 
-    let mut tts = Vec::new();
-    for tt in raw_tts {
-        let user = User::get_by_user_id(pool, tt.user_id)
-            .await
-            .map_err(|_| (axum::http::StatusCode::INTERNAL_SERVER_ERROR, axum::Json(json!({"status": "internal database error"}))))?;
-        let username = user.map_or_else(|| "Unknown".to_string(), |u| u.username);
-        tts.push(SearchTTResponse::from_time_trial_entry(tt, username));
-    }
-        
     Ok((StatusCode::OK, Json(tts)))
 }
\ No newline at end of file
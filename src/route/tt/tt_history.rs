@@ -0,0 +1,85 @@
+// Personal best progression of one user on a car/stage combination: every accepted run, oldest first.
+
+use axum::{Json, extract::State};
+use reqwest::StatusCode;
+use serde_json::json;
+
+use crate::{
+    archive::ArchiveItemRef,
+    db::{tt::{tt_entry::TimeTrialEntry, tt_history::TimeTrialHistoryEntry}, user::User},
+    route::{db_error, tt::resolve_item_ref},
+    state::ThreadSafeState,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TTHistoryRequest {
+    pub username: String,
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "car_id" name
+    #[serde(alias = "car_id")]
+    pub car: ArchiveItemRef,
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "stage_id" name
+    #[serde(alias = "stage_id")]
+    pub stage: ArchiveItemRef,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TTHistoryEntryResponse {
+    /// Pass to /tt/history/fetch to download this run's ghost
+    pub id: String,
+    pub ticks: i32,
+    pub tt_version: i32,
+    /// This run is the current personal best
+    pub is_best: bool,
+    /// This run was faster than every run before it, i.e. it set a new personal best at the time
+    pub improved: bool,
+    /// ISO 8601 format
+    pub created_at: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TTHistoryResponse {
+    /// ID of the time trial; fetching it returns the current personal best ghost
+    pub tt_id: String,
+    pub entries: Vec<TTHistoryEntryResponse>,
+}
+
+pub async fn tt_history(State(state): State<ThreadSafeState>, Json(req): Json<TTHistoryRequest>) -> axum::response::Result<(StatusCode, Json<TTHistoryResponse>)> {
+    let pool = &state.db_pool;
+
+    let car = resolve_item_ref(pool, req.car, "car_id").await?;
+    let stage = resolve_item_ref(pool, req.stage, "stage_id").await?;
+
+    let user_id = User::get_id_from_username(pool, &req.username)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "user not found"}))))?;
+
+    let tt = TimeTrialEntry::filter(pool, Some(user_id), Some(&car), Some(&stage))
+        .await
+        .map_err(db_error)?
+        .pop()
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "no time trials for this car and stage"}))))?;
+
+    let history = TimeTrialHistoryEntry::get_for_time_trial(pool, tt.id)
+        .await
+        .map_err(db_error)?;
+
+    let mut fastest_so_far = i32::MAX;
+    let entries = history
+        .into_iter()
+        .map(|h| {
+            let improved = h.total_ticks < fastest_so_far;
+            fastest_so_far = fastest_so_far.min(h.total_ticks);
+            TTHistoryEntryResponse {
+                id: h.id.to_string(),
+                ticks: h.total_ticks,
+                tt_version: h.tt_version,
+                is_best: h.is_best,
+                improved,
+                created_at: h.created_at.to_string(),
+            }
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(TTHistoryResponse { tt_id: tt.id.to_string(), entries })))
+}
\ No newline at end of file
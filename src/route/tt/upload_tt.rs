@@ -1,29 +1,37 @@
 use crate::{
-    db::{token::UserToken, tt::tt_entry::TimeTrialEntry},
+    archive::{ArchiveItemRef, ArchiveItemType},
+    db::{
+        token::UserToken,
+        tt::{event::TimeTrialEvent, flag::TimeTrialFlag, leaderboard::LeaderboardEntry, tt_entry::TimeTrialEntry, tt_history::TimeTrialHistoryEntry},
+    },
+    metrics::SimulationOutcome,
+    route::{db_error, tt::{get_archived_item, resolve_item_ref, sim_pool_error_response}},
     state::ThreadSafeState,
-    tt::{get_tt_version, validate_upload_tt_file, write_tt_file},
+    tt::{
+        SimulationTarget, delete_tt_history_files, plausibility::check_run, promote_tt_history_file, read_stage_checkpoints,
+        validate_tt, write_tt_history_file,
+    },
 };
+use std::time::Instant;
+
 use axum::{Json, extract::{State, multipart}, http::HeaderMap};
 use reqwest::StatusCode;
 use serde_json::json;
 use sqlx::types::Uuid;
+use tracing::error;
 
 #[derive(Debug, serde::Deserialize)]
 pub struct UploadTTMetadata {
-    pub car_id: String,
-    pub stage_id: String,
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "car_id" name
+    #[serde(alias = "car_id")]
+    pub car: ArchiveItemRef,
+    // {"author": ..., "name": ...}, or a legacy uuid under the old "stage_id" name
+    #[serde(alias = "stage_id")]
+    pub stage: ArchiveItemRef,
 }
 
 // Time trial uploaded as multipart: UploadTTMetadata in "metadata" field, file data in "file" field.
 
-pub fn validate_upload_tt_metadata(metadata: &UploadTTMetadata) -> Result<(Uuid, Uuid), String> {
-    let car_uuid =
-        Uuid::parse_str(&metadata.car_id).map_err(|_| "Invalid car_id UUID".to_string())?;
-    let stage_uuid =
-        Uuid::parse_str(&metadata.stage_id).map_err(|_| "Invalid stage_id UUID".to_string())?;
-    Ok((car_uuid, stage_uuid))
-}
-
 pub async fn upload_tt(
     State(state): State<ThreadSafeState>,
     headers: HeaderMap,
@@ -52,86 +60,159 @@ pub async fn upload_tt(
             Json(json!({"status": "missing or invalid Authorization header"})),
         ))?;
 
-    let pool = &state.lock().await.db_pool;
+    let pool = &state.db_pool;
+    let root = &state.config.filestore;
+    let tt_config = &state.config.tt;
+    let sim_pool = &state.sim_pool;
+    // The simulation runs on another thread, so it needs its own handle to the backend.
+    let backend = state.tt_backend.clone();
     let user_id = UserToken::get_user_by_token(pool, authorization)
         .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"status": "internal database error"})),
-            )
-        })?
+        .map_err(db_error)?
         .ok_or((
             StatusCode::UNAUTHORIZED,
             Json(json!({"status": "invalid token"})),
         ))?
         .user_id;
 
-    let (car_uuid, stage_uuid) = validate_upload_tt_metadata(&metadata)
-        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"status": e}))))?;
-    let res = validate_upload_tt_file(&file_bytes)
-        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"status": e}))))?;
+    state.metrics.upload_size.observe(file_bytes.len() as f64);
+
+    let car = resolve_item_ref(pool, metadata.car, "car_id").await?;
+    let stage = resolve_item_ref(pool, metadata.stage, "stage_id").await?;
+
+    // The run has to reproduce on the archived car and stage it claims, not just on its own.
+    let car_item = get_archived_item(pool, &car, ArchiveItemType::Car, "car_id").await?;
+    let stage_item = get_archived_item(pool, &stage, ArchiveItemType::Stage, "stage_id").await?;
+    let stage_checkpoints = read_stage_checkpoints(&stage_item.path)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"status": e}))))?;
+    let target = SimulationTarget {
+        stage_name: stage_item.name,
+        car_name: car_item.name,
+        stage_checkpoints,
+    };
+
+    let sim_bytes = file_bytes.clone();
+    let simulation = sim_pool
+        .run(move || {
+            let started = Instant::now();
+            (validate_tt(backend.as_ref(), &target, &sim_bytes), started.elapsed())
+        })
+        .await;
+    let (res, info) = match simulation {
+        Ok((result, elapsed)) => {
+            state.metrics.simulation_duration.observe(elapsed.as_secs_f64());
+            result.map_err(|e| {
+                state.metrics.record_simulation(SimulationOutcome::Rejected);
+                (StatusCode::BAD_REQUEST, Json(json!({"status": e})))
+            })?
+        }
+        Err(e) => {
+            state.metrics.record_simulation(SimulationOutcome::from(&e));
+            return Err(sim_pool_error_response(e).into());
+        }
+    };
+
+    // Plausibility is judged against the record as it stood before this run.
+    let record = LeaderboardEntry::page(pool, &stage, Some(&car), tt_config.min_leaderboard_version, 1, 0)
+        .await
+        .map_err(db_error)?
+        .entries
+        .pop();
+    let flag_reasons = check_run(&tt_config.plausibility, res.elapsed_ticks, stage_checkpoints, record.map(|r| r.total_ticks));
+    let flagged = !flag_reasons.is_empty();
+    state.metrics.record_simulation(if flagged { SimulationOutcome::Flagged } else { SimulationOutcome::Accepted });
 
-    // Need to look for existing TTs from this user for the same car and stage.
-    // We only want to store the fastest TT for each user/car/stage combination, so if an existing TT is found, we will only update the DB entry if the new TT is faster.
-    // The file will be replaced in either case since the old one is now invalid.
-    let existing = TimeTrialEntry::filter(pool, Some(user_id), Some(car_uuid), Some(stage_uuid))
+    // The ghost is written before anything is recorded, so that a failed write leaves nothing behind. It goes in
+    // the history first, and replaces the time trial file once the run has been recorded as the best.
+    let history_id = Uuid::new_v4();
+    write_tt_history_file(root, history_id, &file_bytes)
         .await
-        .map_err(|_| {
+        .map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"status": "internal database error"})),
+                Json(json!({"status": format!("failed to write TT file: {}", e)})),
             )
-        })?
-        .pop();
+        })?;
+
+    let recorded: Result<_, sqlx::Error> = async {
+        let mut tx = pool.begin().await?;
 
-    let ver = get_tt_version(&file_bytes)
-        .map_err(|e| (StatusCode::BAD_REQUEST, Json(json!({"status": e}))))?;
-
-    if let Some(existing) = existing {
-        // We can assume that the existing TT is valid.
-        if existing.total_ticks >= res.elapsed_ticks {
-            TimeTrialEntry::update(pool, existing.id, ver, res.elapsed_ticks)
-                .await
-                .map_err(|_| {
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(json!({"status": "internal database error"})),
-                    )
-                })?;
-
-            // The file will be replaced by the caller since the same TT ID is returned for the same user/car/stage combination.
-            write_tt_file(&state, existing.id, &file_bytes)
-                .await
-                .map_err(|e| {
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(json!({"status": format!("failed to write TT file: {}", e)})),
-                    )
-                })?;
+        // The time trial entry always points at the fastest run for each user/car/stage combination; every accepted
+        // run, faster or not, is recorded in the history. The first run creates the entry; a concurrent upload may
+        // get there first, in which case this run is handled as a later one.
+        let (tt_id, is_best) = match TimeTrialEntry::insert(&mut *tx, user_id, &car, &stage, info.replay_version, info.backend_version, res.elapsed_ticks).await? {
+            Some(new_entry) => (new_entry.id, true),
+            None => {
+                let tt_id = TimeTrialEntry::get_id(&mut *tx, user_id, &car, &stage)
+                    .await?
+                    .ok_or(sqlx::Error::RowNotFound)?;
+                // A later run replaces the best if it's faster, or if a backend update has invalidated the best since
+                // it was stored. A flagged run only replaces it once it's approved, so until then the user keeps their
+                // best on leaderboards.
+                let replaced = !flagged
+                    && TimeTrialEntry::update(&mut *tx, tt_id, info.replay_version, info.backend_version, res.elapsed_ticks).await?;
+                (tt_id, replaced)
+            }
+        };
+        // The previous best ghost becomes a regular history entry. It's looked up after the update, which holds the
+        // row lock, so it can't be replaced in the meantime.
+        let previous_best = if is_best {
+            TimeTrialHistoryEntry::get_best(&mut *tx, tt_id).await?.map(|h| h.id)
+        } else {
+            None
+        };
+
+        if is_best {
+            TimeTrialEntry::set_splits(&mut *tx, tt_id, &res.checkpoint_ticks).await?;
+            // Only a first run can be flagged and still be the best; it keeps the time trial off leaderboards until
+            // it's reviewed.
+            if flagged {
+                TimeTrialEntry::set_flagged(&mut *tx, tt_id, true).await?;
+            }
         }
-    } else {
-        let new_entry = TimeTrialEntry::insert(pool, user_id, car_uuid, stage_uuid, ver, res.elapsed_ticks)
-            .await
-            .map_err(|_| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"status": "internal database error"})),
-                )
-            })?;
-
-        write_tt_file(&state, new_entry.id, &file_bytes)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(json!({"status": format!("failed to write TT file: {}", e)})),
-                )
-            })?;
+
+        let history = TimeTrialHistoryEntry::insert(&mut tx, history_id, tt_id, info.replay_version, res.elapsed_ticks, is_best).await?;
+
+        // Flagged runs are entered into events once they're approved.
+        let events = if flagged {
+            TimeTrialFlag::insert(
+                &mut *tx,
+                tt_id,
+                history.id,
+                res.elapsed_ticks,
+                &flag_reasons,
+                info.backend_version,
+                &res.checkpoint_ticks,
+            )
+            .await?;
+            Vec::new()
+        } else {
+            TimeTrialEvent::record_run(&mut *tx, user_id, &car, &stage, &history).await?
+        };
+
+        let pruned = TimeTrialHistoryEntry::prune(&mut *tx, tt_id, tt_config.history_max_entries, tt_config.history_max_age_days).await?;
+
+        tx.commit().await?;
+        Ok((tt_id, is_best, previous_best, events, pruned))
+    }
+    .await;
+    let (tt_id, is_best, previous_best, events, pruned) = match recorded {
+        Ok(recorded) => recorded,
+        Err(e) => {
+            delete_tt_history_files(root, &[history_id]).await;
+            return Err(db_error(e).into());
+        }
+    };
+
+    // The run is recorded by now, so failing the request would only invite a duplicate upload.
+    if is_best && let Err(e) = promote_tt_history_file(root, tt_id, history_id, previous_best).await {
+        error!(%tt_id, %history_id, error = %e, "failed to move new best TT file into place");
     }
+    delete_tt_history_files(root, &pruned).await;
 
     Ok((
         StatusCode::OK,
-        Json(json!({"status": "file validated successfully"})),
+        Json(json!({"status": "file validated successfully", "personal_best": is_best, "events": events, "pending_review": flagged})),
     ))
 }
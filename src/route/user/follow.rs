@@ -0,0 +1,78 @@
+// Following other users. Users who follow each other are friends, which matters for ghost visibility.
+
+use axum::{Json, extract::State, http::{HeaderMap, StatusCode}};
+use serde_json::json;
+
+use crate::{
+    db::{token::UserToken, user::User, user_follow},
+    route::db_error,
+    state::ThreadSafeState,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FollowRequest {
+    pub username: String,
+}
+
+/// Resolves the authenticated user and the user named in the request.
+async fn resolve_users(
+    pool: &sqlx::PgPool,
+    headers: &HeaderMap,
+    username: &str,
+) -> Result<(i32, i32), (StatusCode, Json<serde_json::Value>)> {
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "missing or invalid Authorization header"}))))?;
+    let user_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
+
+    let other_id = User::get_id_from_username(pool, username)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "user not found"}))))?;
+    if other_id == user_id {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"status": "cannot follow yourself"}))));
+    }
+
+    Ok((user_id, other_id))
+}
+
+pub async fn follow(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<FollowRequest>,
+) -> axum::response::Result<(StatusCode, Json<serde_json::Value>)> {
+    let pool = state.db_pool.clone();
+    let (user_id, other_id) = resolve_users(&pool, &headers, &req.username).await?;
+
+    let followed = user_follow::follow(&pool, user_id, other_id)
+        .await
+        .map_err(db_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"status": if followed { "followed" } else { "already following" }})),
+    ))
+}
+
+pub async fn unfollow(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<FollowRequest>,
+) -> axum::response::Result<(StatusCode, Json<serde_json::Value>)> {
+    let pool = state.db_pool.clone();
+    let (user_id, other_id) = resolve_users(&pool, &headers, &req.username).await?;
+
+    let unfollowed = user_follow::unfollow(&pool, user_id, other_id)
+        .await
+        .map_err(db_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"status": if unfollowed { "unfollowed" } else { "not following" }})),
+    ))
+}
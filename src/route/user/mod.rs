@@ -0,0 +1,3 @@
+pub mod follow;
+pub mod profile;
+pub mod settings;
@@ -0,0 +1,130 @@
+// Public profile of a user, as shown on an author page in the client. Contains nothing private:
+// linked login providers are listed by name only, without their account ids.
+
+use std::collections::BTreeMap;
+
+use axum::{Json, extract::State, http::{HeaderMap, StatusCode}};
+use serde_json::json;
+
+use crate::{
+    archive::ArchiveItemType,
+    db::{
+        archive::archive_item::ArchiveItem, oauth2::discord_oauth2::DiscordOauth2AccountEntry,
+        tt::tt_entry::TimeTrialEntry, user::User,
+    },
+    route::{db_error, tt::{optional_user, search_tt::SearchTTResponse}},
+    state::ThreadSafeState,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UserProfileRequest {
+    pub username: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ProfileItem {
+    pub author: String,
+    pub name: String,
+    /// Legacy uuid, for older clients. None if the item doesn't have one.
+    pub id: Option<String>,
+    /// ISO 8601 format
+    pub created_at: String,
+}
+
+#[derive(Debug, serde::Serialize, Default)]
+pub struct ProfileItemList {
+    pub count: usize,
+    pub items: Vec<ProfileItem>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct UserProfileResponse {
+    pub username: String,
+    /// ISO 8601 format
+    pub created_at: String,
+    /// e.g. "local", "discord"
+    pub providers: Vec<String>,
+    /// Keyed by item type ("car", "stage", "stage_piece", "wheel"); every type is present even if empty.
+    pub items: BTreeMap<String, ProfileItemList>,
+    /// One entry per car/stage combination, fastest first.
+    pub personal_bests: Vec<SearchTTResponse>,
+}
+
+pub async fn profile(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<UserProfileRequest>,
+) -> axum::response::Result<(StatusCode, Json<UserProfileResponse>)> {
+    let pool = &state.db_pool;
+
+    let user_id = User::get_id_from_username(pool, &req.username)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "user not found"}))))?;
+
+    let user = User::get_by_user_id(pool, user_id)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, Json(json!({"status": "user not found"}))))?;
+
+    let mut providers = Vec::new();
+    if user.phash.is_some() {
+        providers.push("local".to_owned());
+    }
+    let discord = DiscordOauth2AccountEntry::lookup_user_id(pool, user_id)
+        .await
+        .map_err(db_error)?;
+    if discord.is_some() {
+        providers.push("discord".to_owned());
+    }
+
+    let mut items: BTreeMap<String, ProfileItemList> = ArchiveItemType::ALL
+        .iter()
+        .map(|t| (t.to_string(), ProfileItemList::default()))
+        .collect();
+
+    let owned = ArchiveItem::get_by_owner(pool, user_id)
+        .await
+        .map_err(db_error)?;
+    for item in owned {
+        let list = items.entry(item.r#type.clone()).or_default();
+        list.count += 1;
+        list.items.push(ProfileItem {
+            author: item.author,
+            name: item.name,
+            id: item.legacy_id.map(|id| id.hyphenated().to_string()),
+            created_at: item.created_at.map_or_else(String::new, |dt| dt.to_string()),
+        });
+    }
+    for list in items.values_mut() {
+        list.items.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    // A time trial holds the user's best run on its car and stage, so every entry is a personal best. Runs kept off
+    // the leaderboards are only shown to the user themselves.
+    let viewer_id = optional_user(pool, &headers).await?;
+    let tts = TimeTrialEntry::personal_bests(pool, user_id, viewer_id == Some(user_id))
+        .await
+        .map_err(db_error)?;
+
+    let keys = tts.iter().flat_map(|tt| [tt.car(), tt.stage()]).collect::<Vec<_>>();
+    let legacy_ids = ArchiveItem::get_legacy_ids(pool, &keys)
+        .await
+        .map_err(db_error)?;
+
+    let personal_bests = tts
+        .into_iter()
+        .map(|tt| SearchTTResponse::from_time_trial_entry(tt, user.username.clone(), &legacy_ids))
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(UserProfileResponse {
+            username: user.username,
+            created_at: user.created_at.map_or_else(String::new, |dt| dt.to_string()),
+            providers,
+            items,
+            personal_bests,
+        }),
+    ))
+}
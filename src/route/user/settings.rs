@@ -0,0 +1,61 @@
+// Reads and updates the authenticated user's settings. Fields left out of the request are unchanged, so an empty
+// request just returns the current settings.
+
+use axum::{Json, extract::State, http::{HeaderMap, StatusCode}};
+use serde_json::json;
+
+use crate::{
+    db::{
+        token::UserToken,
+        user_follow::get_following,
+        user_settings::{GhostVisibility, get_ghost_visibility, set_ghost_visibility},
+    },
+    route::db_error,
+    state::ThreadSafeState,
+};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UserSettingsRequest {
+    pub ghost_visibility: Option<GhostVisibility>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct UserSettingsResponse {
+    pub ghost_visibility: GhostVisibility,
+    /// Usernames of the users being followed
+    pub following: Vec<String>,
+}
+
+pub async fn settings(
+    State(state): State<ThreadSafeState>,
+    headers: HeaderMap,
+    Json(req): Json<UserSettingsRequest>,
+) -> axum::response::Result<(StatusCode, Json<UserSettingsResponse>)> {
+    let authorization = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "missing or invalid Authorization header"}))))?;
+
+    let pool = &state.db_pool;
+
+    let user_id = UserToken::get_user_by_token(pool, authorization)
+        .await
+        .map_err(db_error)?
+        .ok_or((StatusCode::UNAUTHORIZED, Json(json!({"status": "invalid token"}))))?
+        .user_id;
+
+    if let Some(visibility) = req.ghost_visibility {
+        set_ghost_visibility(pool, user_id, visibility)
+            .await
+            .map_err(db_error)?;
+    }
+
+    let ghost_visibility = get_ghost_visibility(pool, user_id)
+        .await
+        .map_err(db_error)?;
+    let following = get_following(pool, user_id)
+        .await
+        .map_err(db_error)?;
+
+    Ok((StatusCode::OK, Json(UserSettingsResponse { ghost_visibility, following })))
+}
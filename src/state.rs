@@ -1,18 +1,21 @@
-use std::sync::Arc;
+use std::sync::{Arc, atomic::{AtomicU8, Ordering}};
 
-use tokio::sync::Mutex;
+use crate::{config::Config, metrics::Metrics, tt::{backend::TimeTrialBackend, pool::SimulationPool}};
 
-use crate::config::Config;
-
-#[derive(Clone)]
+/// Everything in here is either immutable after startup or shareable on its own (the pools and clients),
+/// so handlers read it without taking a lock.
 pub struct State {
     pub db_pool: sqlx::PgPool,
-    pub index_state: IndexState,
+    pub index_state: SharedIndexState,
     pub config: Config,
-    pub req_client: reqwest::Client
+    pub req_client: reqwest::Client,
+    pub sim_pool: SimulationPool,
+    pub tt_backend: Arc<dyn TimeTrialBackend>,
+    pub metrics: Metrics
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
 pub enum IndexState {
     /// Archive is searchable
     Healthy,
@@ -21,5 +24,35 @@ pub enum IndexState {
     /// Index is incomplete; searching is disabled. Only happens in the event of an error when indexing.
     Unhealthy
 }
+impl IndexState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexState::Healthy => "healthy",
+            IndexState::Regenerating => "regenerating",
+            IndexState::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// The only part of the state that changes at runtime, kept in an atomic so readers never wait on the indexer.
+pub struct SharedIndexState(AtomicU8);
+
+impl SharedIndexState {
+    pub fn new(state: IndexState) -> Self {
+        Self(AtomicU8::new(state as u8))
+    }
+
+    pub fn get(&self) -> IndexState {
+        match self.0.load(Ordering::Acquire) {
+            0 => IndexState::Healthy,
+            1 => IndexState::Regenerating,
+            _ => IndexState::Unhealthy,
+        }
+    }
+
+    pub fn set(&self, state: IndexState) {
+        self.0.store(state as u8, Ordering::Release);
+    }
+}
 
-pub type ThreadSafeState = Arc<Mutex<State>>;
\ No newline at end of file
+pub type ThreadSafeState = Arc<State>;
@@ -0,0 +1,142 @@
+// The simulation backend reads time trial files and replays them. The real one is NFMWorld.Library over FFI
+// (the `ffi` feature); `FakeBackend` stands in for it where the .NET SDK isn't available.
+
+use std::{fmt, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TimeTrialInfo {
+    pub checkpoint_count: i32,
+    pub tick_count: i32,
+    pub replay_version: i32,
+    pub backend_version: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationResult {
+    pub elapsed_ticks: i32,
+    pub expected_ticks: i32,
+    /// Elapsed ticks at each checkpoint, in the order they were passed. Empty if the backend doesn't report splits.
+    pub checkpoint_ticks: Vec<i32>,
+}
+
+#[derive(Debug)]
+pub enum BackendError {
+    /// The arguments can't be passed to the backend, e.g. a name containing a NUL byte.
+    InvalidArgument(String),
+    /// The backend threw; for the native backend, this is its managed exception.
+    Exception {
+        type_name: String,
+        message: String,
+        stack_trace: String,
+    },
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidArgument(e) => write!(f, "invalid argument: {e}"),
+            // The stack trace is only included with `{:#}`, since it shouldn't end up in responses to clients.
+            Self::Exception { type_name, message, stack_trace } if f.alternate() => {
+                write!(f, "{type_name}: {message}\n{stack_trace}")
+            }
+            Self::Exception { type_name, message, .. } => write!(f, "{type_name}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+pub trait TimeTrialBackend: Send + Sync {
+    fn get_info(&self, data: &[u8]) -> Result<TimeTrialInfo, BackendError>;
+
+    fn simulate(&self, stage_name: &str, car_names: &[&str], data: &[u8]) -> Result<SimulationResult, BackendError>;
+
+    /// A small ghost bundled with the server that `get_info` accepts, so readiness can exercise the backend without
+    /// depending on stored runs. None if no fixture has been recorded for the backend.
+    fn probe_ghost(&self) -> Option<&'static [u8]>;
+}
+
+// Never defaults to the fake backend, even in builds that can't use the native one: a server that accepts forged
+// runs must not come about by leaving a setting out.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    #[default]
+    Native,
+    Fake,
+}
+
+pub fn create_backend(kind: BackendKind) -> Result<Arc<dyn TimeTrialBackend>, String> {
+    match kind {
+        #[cfg(feature = "ffi")]
+        BackendKind::Native => Ok(Arc::new(crate::tt::native::NativeBackend)),
+        #[cfg(not(feature = "ffi"))]
+        BackendKind::Native => Err("the native time trial backend requires the `ffi` feature; set tt.backend = \"fake\" for development".to_string()),
+        BackendKind::Fake => Ok(Arc::new(FakeBackend)),
+    }
+}
+
+/// Deterministic stand-in for the native backend. Instead of a real replay, it expects a 12-byte header of
+/// little-endian i32s: checkpoint count, tick count and replay version. Anything after the header is ignored.
+/// Checkpoints are spread evenly over the run, the last one being passed on the final tick.
+///
+/// Every simulation reproduces exactly, so only use this for development and testing.
+pub struct FakeBackend;
+
+impl FakeBackend {
+    pub const BACKEND_VERSION: i32 = 0;
+
+    fn read_header(data: &[u8]) -> Result<[i32; 3], BackendError> {
+        if data.len() < 12 {
+            return Err(BackendError::Exception {
+                type_name: "FakeBackend.InvalidData".to_string(),
+                message: "time trial data is shorter than the 12-byte header".to_string(),
+                stack_trace: String::new(),
+            });
+        }
+        let field = |i: usize| i32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+        Ok([field(0), field(1), field(2)])
+    }
+
+    // The native backend takes names as C strings, so the same names are rejected here.
+    fn check_name(name: &str, what: &str) -> Result<(), BackendError> {
+        if name.contains('\0') {
+            return Err(BackendError::InvalidArgument(format!("{what} contains a NUL byte")));
+        }
+        Ok(())
+    }
+}
+
+impl TimeTrialBackend for FakeBackend {
+    fn get_info(&self, data: &[u8]) -> Result<TimeTrialInfo, BackendError> {
+        let [checkpoint_count, tick_count, replay_version] = Self::read_header(data)?;
+        Ok(TimeTrialInfo {
+            checkpoint_count,
+            tick_count,
+            replay_version,
+            backend_version: Self::BACKEND_VERSION,
+        })
+    }
+
+    fn simulate(&self, stage_name: &str, car_names: &[&str], data: &[u8]) -> Result<SimulationResult, BackendError> {
+        Self::check_name(stage_name, "stage name")?;
+        for name in car_names {
+            Self::check_name(name, "car name")?;
+        }
+        let [checkpoint_count, tick_count, _] = Self::read_header(data)?;
+        let checkpoint_ticks = (1..=checkpoint_count.max(0) as i64)
+            .map(|i| (tick_count as i64 * i / checkpoint_count as i64) as i32)
+            .collect();
+        Ok(SimulationResult {
+            elapsed_ticks: tick_count,
+            expected_ticks: tick_count,
+            checkpoint_ticks,
+        })
+    }
+
+    fn probe_ghost(&self) -> Option<&'static [u8]> {
+        Some(include_bytes!("../../fixtures/fake.timetrial"))
+    }
+}
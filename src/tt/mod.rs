@@ -1,30 +1,111 @@
+use std::io;
+
 use sqlx::types::Uuid;
-use tokio::fs::{read, write};
-use crate::{ffi::SimulateTimeTrialResult, state::ThreadSafeState};
+use tokio::fs::{hard_link, read, remove_file, rename, write};
+use tracing::warn;
+use crate::{archive::parse::count_checkpoints, tt::backend::{SimulationResult, TimeTrialBackend, TimeTrialInfo}};
+
+pub mod backend;
+#[cfg(feature = "ffi")]
+pub mod native;
+pub mod plausibility;
+pub mod pool;
+pub mod revalidate;
+pub mod storage;
+pub mod visibility;
 
 pub fn get_tt_file_path(root: &str, tt_id: Uuid) -> String {
     format!("{}/tt/{}.timetrial", root, tt_id.hyphenated())
 }
 
-pub async fn write_tt_file(state: &ThreadSafeState, tt_id: Uuid, data: &[u8]) -> Result<(), std::io::Error> {
-    let path = get_tt_file_path(&state.lock().await.config.filestore, tt_id);
-    write(path, data).await
+/// Ghost of a superseded run; see `TimeTrialHistoryEntry`.
+pub fn get_tt_history_file_path(root: &str, history_id: Uuid) -> String {
+    format!("{}/tt/history/{}.timetrial", root, history_id.hyphenated())
+}
+
+pub fn ensure_tt_dirs_exist(root: &str) -> io::Result<()> {
+    std::fs::create_dir_all(format!("{root}/tt/history"))
+}
+
+// Takes the filestore root rather than the state, so it can be used from background tasks as well as handlers.
+// Only history files are written directly; a new best is written here and then promoted with
+// `promote_tt_history_file`.
+pub async fn write_tt_history_file(root: &str, history_id: Uuid, data: &[u8]) -> Result<(), std::io::Error> {
+    write_stored(&get_tt_history_file_path(root, history_id), data).await
 }
 
-pub async fn read_tt_file(state: &ThreadSafeState, tt_id: Uuid) -> Result<Vec<u8>, std::io::Error> {
-    let path = get_tt_file_path(&state.lock().await.config.filestore, tt_id);
+// Compressing a replay of several MB takes long enough that it shouldn't run on the async runtime.
+async fn write_stored(path: &str, data: &[u8]) -> Result<(), std::io::Error> {
+    let data = data.to_vec();
+    let stored = tokio::task::spawn_blocking(move || storage::encode(&data))
+        .await
+        .map_err(io::Error::other)??;
+    write(path, stored).await
+}
+
+/// Makes the ghost of a history entry the time trial's file, once the entry has been recorded as the best run.
+/// The previous best ghost is kept in the history under `previous_best_id`: it's hard linked there first, so that
+/// the time trial file is replaced in a single rename and is never missing. A missing previous ghost is not an
+/// error, since runs recorded before the history existed may not have one.
+pub async fn promote_tt_history_file(
+    root: &str,
+    tt_id: Uuid,
+    history_id: Uuid,
+    previous_best_id: Option<Uuid>,
+) -> Result<(), std::io::Error> {
+    let tt_path = get_tt_file_path(root, tt_id);
+    if let Some(previous_best_id) = previous_best_id {
+        match hard_link(&tt_path, get_tt_history_file_path(root, previous_best_id)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    rename(get_tt_history_file_path(root, history_id), tt_path).await
+}
+
+/// Best effort; failures are logged, since the DB entries are already gone at this point.
+pub async fn delete_tt_history_files(root: &str, history_ids: &[Uuid]) {
+    for id in history_ids {
+        if let Err(e) = remove_file(get_tt_history_file_path(root, *id)).await {
+            warn!(history_id = %id, error = %e, "failed to remove TT history file");
+        }
+    }
+}
+
+/// Removes the ghosts of a deleted time trial: its own file and those of its non-best history entries.
+/// Best effort, like `delete_tt_history_files`.
+pub async fn delete_tt_files(root: &str, tt_id: Uuid, history_ids: &[Uuid]) {
+    if let Err(e) = remove_file(get_tt_file_path(root, tt_id)).await {
+        warn!(%tt_id, error = %e, "failed to remove TT file");
+    }
+    delete_tt_history_files(root, history_ids).await;
+}
+
+/// Reads a time trial file as stored, i.e. possibly compressed; see `storage`.
+pub async fn read_stored_tt_file_at(path: &str) -> Result<Vec<u8>, std::io::Error> {
     read(path).await
 }
 
-pub fn validate_upload_tt_file(file_bytes: &[u8]) -> Result<SimulateTimeTrialResult, String> {
+/// Reads the replay in a stored time trial file, decompressing it if needed. Like compression in `write_stored`,
+/// decompression runs off the async runtime.
+pub async fn read_tt_file_at(path: &str) -> Result<Vec<u8>, std::io::Error> {
+    let stored = read(path).await?;
+    tokio::task::spawn_blocking(move || storage::decode(&stored))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Replays a run on the given stage and car, and checks that it reproduces.
+pub fn validate_upload_tt_file(backend: &dyn TimeTrialBackend, stage_name: &str, car_name: &str, file_bytes: &[u8]) -> Result<SimulationResult, String> {
     if file_bytes.len() > 10 * 1024 * 1024 {
         return Err("File size exceeds 10 MB limit".to_string());
     }
 
-    let sim_result = unsafe { crate::ffi::nfmw_simulate_tt(file_bytes.as_ptr() as *const _) };
-    if sim_result.has_error {
-        return Err(format!("TT simulation failed: {}", String::from_utf8_lossy(&sim_result.exception.message)));
-    }
+    let sim_result = backend.simulate(stage_name, &[car_name], file_bytes)
+        .map_err(|e| {
+            warn!(error = %format_args!("{e:#}"), "TT simulation failed");
+            format!("TT simulation failed: {}", e)
+        })?;
 
     if sim_result.elapsed_ticks <= 0 {
         return Err("Simulated TT has non-positive tick count, invalid TT data".to_string());
@@ -41,10 +122,108 @@ pub fn validate_upload_tt_file(file_bytes: &[u8]) -> Result<SimulateTimeTrialRes
     Ok(sim_result)
 }
 
-pub fn get_tt_version(file_bytes: &[u8]) -> Result<i32, String> {
-    let info = unsafe { crate::ffi::nfmw_get_tt_info(file_bytes.as_ptr() as *const _) };
-    if info.has_error {
-        return Err(format!("TT info fetch failed: {}", String::from_utf8_lossy(&info.exception.message)));
+pub fn get_tt_info(backend: &dyn TimeTrialBackend, file_bytes: &[u8]) -> Result<TimeTrialInfo, String> {
+    let info = backend.get_info(file_bytes)
+        .map_err(|e| {
+            warn!(error = %format_args!("{e:#}"), "TT info fetch failed");
+            format!("TT info fetch failed: {}", e)
+        })?;
+    Ok(info)
+}
+
+/// The archived stage and car a run claims to have been set on.
+#[derive(Clone)]
+pub struct SimulationTarget {
+    pub stage_name: String,
+    pub car_name: String,
+    pub stage_checkpoints: i32,
+}
+
+/// Counts the checkpoints of an archived stage from its file.
+pub async fn read_stage_checkpoints(stage_path: &str) -> Result<i32, String> {
+    let content = tokio::fs::read_to_string(stage_path)
+        .await
+        .map_err(|e| format!("failed to read stage file: {}", e))?;
+    count_checkpoints(&content).map_err(|e| format!("failed to parse stage file: {}", e))
+}
+
+/// Checks that a run passes through every checkpoint of its stage and reproduces on its stage and car.
+pub fn validate_tt(backend: &dyn TimeTrialBackend, target: &SimulationTarget, file_bytes: &[u8]) -> Result<(SimulationResult, TimeTrialInfo), String> {
+    let info = get_tt_info(backend, file_bytes)?;
+    if info.checkpoint_count != target.stage_checkpoints {
+        return Err(format!(
+            "TT has {} checkpoints, but the stage has {}",
+            info.checkpoint_count, target.stage_checkpoints
+        ));
+    }
+    let mut res = validate_upload_tt_file(backend, &target.stage_name, &target.car_name, file_bytes)?;
+    if !res.checkpoint_ticks.is_empty() && res.checkpoint_ticks.len() != target.stage_checkpoints as usize {
+        // Not the run's fault; it reproduced, so it's accepted, just without splits.
+        warn!(
+            splits = res.checkpoint_ticks.len(),
+            checkpoints = target.stage_checkpoints,
+            "simulation reported a different number of splits than the stage has checkpoints; discarding them"
+        );
+        res.checkpoint_ticks.clear();
+    }
+    Ok((res, info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tt::backend::FakeBackend;
+
+    fn target(stage_checkpoints: i32) -> SimulationTarget {
+        SimulationTarget {
+            stage_name: "stage".to_string(),
+            car_name: "car".to_string(),
+            stage_checkpoints,
+        }
+    }
+
+    // A file the fake backend reads as a run through `checkpoints` checkpoints in `ticks` ticks.
+    fn fake_run(checkpoints: i32, ticks: i32) -> Vec<u8> {
+        [checkpoints, ticks, 1].iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn accepts_a_run_that_reproduces() {
+        let (res, info) = validate_tt(&FakeBackend, &target(3), &fake_run(3, 300)).unwrap();
+        assert_eq!(res.elapsed_ticks, 300);
+        assert_eq!(res.checkpoint_ticks, vec![100, 200, 300]);
+        assert_eq!(info.replay_version, 1);
+        assert_eq!(info.backend_version, FakeBackend::BACKEND_VERSION);
     }
-    Ok(info.replay_version)
-}
\ No newline at end of file
+
+    #[test]
+    fn rejects_a_run_with_the_wrong_number_of_checkpoints() {
+        let err = validate_tt(&FakeBackend, &target(4), &fake_run(3, 300)).unwrap_err();
+        assert_eq!(err, "TT has 3 checkpoints, but the stage has 4");
+    }
+
+    #[test]
+    fn rejects_a_run_with_no_ticks() {
+        assert!(validate_tt(&FakeBackend, &target(3), &fake_run(3, 0)).is_err());
+    }
+
+    #[test]
+    fn rejects_data_the_backend_cant_read() {
+        let err = validate_tt(&FakeBackend, &target(3), b"short").unwrap_err();
+        assert!(err.starts_with("TT info fetch failed"), "{err}");
+    }
+
+    #[test]
+    fn rejects_files_over_the_size_limit() {
+        let mut data = fake_run(3, 300);
+        data.resize(10 * 1024 * 1024 + 1, 0);
+        assert_eq!(validate_tt(&FakeBackend, &target(3), &data).unwrap_err(), "File size exceeds 10 MB limit");
+    }
+
+    #[test]
+    fn rejects_names_the_backend_cant_take() {
+        let target = SimulationTarget { car_name: "car\0".to_string(), ..target(3) };
+        let err = validate_tt(&FakeBackend, &target, &fake_run(3, 300)).unwrap_err();
+        assert_eq!(err, "TT simulation failed: invalid argument: car name contains a NUL byte");
+    }
+}
@@ -0,0 +1,99 @@
+// Safe wrapper around the NFMWorld.Library bindings in `crate::ffi`, exposed as a `TimeTrialBackend`.
+// This is the only module that should call into the native library; everything else goes through the
+// functions here, which own the argument buffers for the duration of the call.
+
+use std::ffi::CString;
+
+use crate::{
+    ffi::{CarInfoUnmanaged, GetTTInfoArgs, NativeException, SimulateTimeTrialArgs, nfmw_get_tt_info, nfmw_simulate_tt},
+    tt::backend::{BackendError, SimulationResult, TimeTrialBackend, TimeTrialInfo},
+};
+
+/// NFMWorld.Library, called in-process.
+pub struct NativeBackend;
+
+fn exception_to_error(e: &NativeException) -> BackendError {
+    BackendError::Exception {
+        type_name: decode_buffer(&e.type_name),
+        message: decode_buffer(&e.message),
+        stack_trace: decode_buffer(&e.stack_trace),
+    }
+}
+
+// The exception strings are NUL-terminated inside fixed-size buffers.
+fn decode_buffer(buf: &[u8]) -> String {
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+fn data_length(data: &[u8]) -> Result<i32, BackendError> {
+    i32::try_from(data.len()).map_err(|_| BackendError::InvalidArgument("time trial data is too large".to_string()))
+}
+
+fn to_c_string(s: &str, what: &str) -> Result<CString, BackendError> {
+    CString::new(s).map_err(|_| BackendError::InvalidArgument(format!("{what} contains a NUL byte")))
+}
+
+impl TimeTrialBackend for NativeBackend {
+    fn get_info(&self, data: &[u8]) -> Result<TimeTrialInfo, BackendError> {
+        let args = GetTTInfoArgs {
+            time_trial_data: data.as_ptr(),
+            time_trial_data_length: data_length(data)?,
+        };
+
+        // SAFETY: `args` points into `data`, which outlives the call, and the length matches the slice.
+        let result = unsafe { nfmw_get_tt_info(&args) };
+        if result.has_error {
+            return Err(exception_to_error(&result.exception));
+        }
+
+        Ok(TimeTrialInfo {
+            checkpoint_count: result.checkpoint_count,
+            tick_count: result.tick_count,
+            replay_version: result.replay_version,
+            backend_version: result.backend_version,
+        })
+    }
+
+    fn simulate(&self, stage_name: &str, car_names: &[&str], data: &[u8]) -> Result<SimulationResult, BackendError> {
+        let stage_name = to_c_string(stage_name, "stage name")?;
+        let car_names = car_names
+            .iter()
+            .map(|name| to_c_string(name, "car name"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let cars = car_names
+            .iter()
+            .map(|name| CarInfoUnmanaged { car_name: name.as_ptr() as *const u8 })
+            .collect::<Vec<_>>();
+        let car_count = i32::try_from(cars.len()).map_err(|_| BackendError::InvalidArgument("too many cars".to_string()))?;
+
+        let args = SimulateTimeTrialArgs {
+            stage_name: stage_name.as_ptr() as *const u8,
+            cars: cars.as_ptr(),
+            car_count,
+            time_trial_data: data.as_ptr(),
+            time_trial_data_length: data_length(data)?,
+        };
+
+        // SAFETY: every pointer in `args` borrows from `stage_name`, `car_names`, `cars` or `data`, all of which
+        // outlive the call. The strings are NUL-terminated by `CString`.
+        let result = unsafe { nfmw_simulate_tt(&args) };
+        if result.has_error {
+            return Err(exception_to_error(&result.exception));
+        }
+
+        Ok(SimulationResult {
+            elapsed_ticks: result.elapsed_ticks,
+            expected_ticks: result.expected_ticks,
+            // SimulateTimeTrialResult doesn't carry splits yet. Once NFMWorld.Library reports them and ffi.rs is
+            // regenerated, copy them over here.
+            checkpoint_ticks: Vec::new(),
+        })
+    }
+
+    // There's no ghost recorded with NFMWorld.Library in fixtures/ yet; until there is, readiness probes with the
+    // newest stored ghost instead.
+    fn probe_ghost(&self) -> Option<&'static [u8]> {
+        None
+    }
+}
@@ -0,0 +1,73 @@
+// A run that reproduces in simulation can still be tool-assisted, or exploit a bug in the replay format. These
+// checks catch the obvious cases; a run that trips one is held for a moderator instead of going on leaderboards.
+
+use crate::config::PlausibilityConfig;
+
+/// Returns why the run looks implausible, one reason per failed check. Empty if it passed them all.
+/// `record_ticks` is the current record for the run's car and stage, if there is one.
+pub fn check_run(config: &PlausibilityConfig, total_ticks: i32, stage_checkpoints: i32, record_ticks: Option<i32>) -> Vec<String> {
+    let mut reasons = Vec::new();
+
+    if let (Some(max_percent), Some(record)) = (config.max_record_improvement_percent, record_ticks) {
+        let improvement = (record - total_ticks) as f64 / record as f64 * 100.0;
+        if record > 0 && improvement > max_percent {
+            reasons.push(format!(
+                "beats the record of {record} ticks by {improvement:.1}%, more than the allowed {max_percent}%"
+            ));
+        }
+    }
+
+    if let Some(min_per_checkpoint) = config.min_ticks_per_checkpoint {
+        let min_ticks = min_per_checkpoint as i64 * stage_checkpoints as i64;
+        if (total_ticks as i64) < min_ticks {
+            reasons.push(format!(
+                "finishes in {total_ticks} ticks, under the minimum of {min_ticks} for {stage_checkpoints} checkpoints"
+            ));
+        }
+    }
+
+    reasons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(max_record_improvement_percent: Option<f64>, min_ticks_per_checkpoint: Option<i32>) -> PlausibilityConfig {
+        PlausibilityConfig { max_record_improvement_percent, min_ticks_per_checkpoint }
+    }
+
+    #[test]
+    fn improvement_up_to_the_limit_passes() {
+        let config = config(Some(10.0), None);
+        assert!(check_run(&config, 900, 3, Some(1000)).is_empty());
+        assert!(check_run(&config, 1200, 3, Some(1000)).is_empty());
+        assert_eq!(check_run(&config, 899, 3, Some(1000)).len(), 1);
+    }
+
+    #[test]
+    fn improvement_is_not_checked_without_a_record() {
+        let config = config(Some(10.0), None);
+        assert!(check_run(&config, 1, 3, None).is_empty());
+        assert!(check_run(&config, 1, 3, Some(0)).is_empty());
+    }
+
+    #[test]
+    fn runs_under_the_minimum_per_checkpoint_are_flagged() {
+        let config = config(None, Some(100));
+        assert!(check_run(&config, 300, 3, None).is_empty());
+        assert_eq!(check_run(&config, 299, 3, None).len(), 1);
+        assert!(check_run(&config, 0, 0, None).is_empty());
+    }
+
+    #[test]
+    fn every_failed_check_is_reported() {
+        let config = config(Some(10.0), Some(100));
+        assert_eq!(check_run(&config, 200, 3, Some(1000)).len(), 2);
+    }
+
+    #[test]
+    fn disabled_checks_pass_everything() {
+        assert!(check_run(&config(None, None), 1, 3, Some(1000)).is_empty());
+    }
+}
@@ -0,0 +1,137 @@
+// Native time trial work is synchronous and CPU-heavy, so it's kept off the async runtime and run on a
+// size-limited set of blocking threads. Jobs beyond the worker count wait in a bounded queue; once that's
+// full, new jobs are turned away rather than piling up.
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use tokio::sync::Semaphore;
+
+use crate::config::TimeTrialConfig;
+
+#[derive(Debug)]
+pub enum SimulationPoolError {
+    /// All workers are busy and the queue is full.
+    QueueFull,
+    /// The job didn't finish within the configured timeout.
+    TimedOut,
+    /// The job panicked.
+    Failed,
+}
+
+impl fmt::Display for SimulationPoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull => write!(f, "simulation queue is full"),
+            Self::TimedOut => write!(f, "simulation timed out"),
+            Self::Failed => write!(f, "simulation worker failed"),
+        }
+    }
+}
+
+impl std::error::Error for SimulationPoolError {}
+
+#[derive(Clone)]
+pub struct SimulationPool {
+    /// Held for as long as a job is queued or running; bounds workers + queue.
+    slots: Arc<Semaphore>,
+    /// Held while a job runs on a blocking thread; bounds workers.
+    workers: Arc<Semaphore>,
+    timeout: Duration,
+}
+
+impl SimulationPool {
+    pub fn new(config: &TimeTrialConfig) -> Self {
+        let workers = config.simulation_workers.max(1);
+        SimulationPool {
+            slots: Arc::new(Semaphore::new(workers + config.simulation_queue_size)),
+            workers: Arc::new(Semaphore::new(workers)),
+            timeout: Duration::from_secs(config.simulation_timeout_secs),
+        }
+    }
+
+    /// Runs `job` on a worker thread. The timeout covers both waiting in the queue and running.
+    ///
+    /// Native calls can't be interrupted, so a job that times out keeps its worker until it actually finishes;
+    /// the caller just stops waiting for it.
+    pub async fn run<T, F>(&self, job: F) -> Result<T, SimulationPoolError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let slot = self.slots.clone().try_acquire_owned().map_err(|_| SimulationPoolError::QueueFull)?;
+        let workers = self.workers.clone();
+
+        let work = async move {
+            let worker = workers.acquire_owned().await.map_err(|_| SimulationPoolError::Failed)?;
+            tokio::task::spawn_blocking(move || {
+                let result = job();
+                drop(worker);
+                drop(slot);
+                result
+            })
+            .await
+            .map_err(|_| SimulationPoolError::Failed)
+        };
+
+        tokio::time::timeout(self.timeout, work)
+            .await
+            .map_err(|_| SimulationPoolError::TimedOut)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(workers: usize, queue_size: usize, timeout_secs: u64) -> SimulationPool {
+        SimulationPool::new(&TimeTrialConfig {
+            simulation_workers: workers,
+            simulation_queue_size: queue_size,
+            simulation_timeout_secs: timeout_secs,
+            ..TimeTrialConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn runs_jobs_and_returns_their_result() {
+        assert_eq!(pool(1, 0, 5).run(|| 2 + 2).await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn turns_jobs_away_once_workers_and_queue_are_full() {
+        let pool = pool(1, 1, 5);
+        let (release, wait) = std::sync::mpsc::channel::<()>();
+        let running = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(move || wait.recv().unwrap()).await }
+        });
+        let queued = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| ()).await }
+        });
+        // Let both jobs take their slots
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(matches!(pool.run(|| ()).await, Err(SimulationPoolError::QueueFull)));
+
+        release.send(()).unwrap();
+        running.await.unwrap().unwrap();
+        queued.await.unwrap().unwrap();
+        // The slots are given back once the jobs are done
+        pool.run(|| ()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stops_waiting_after_the_timeout() {
+        let res = pool(1, 0, 1).run(|| std::thread::sleep(Duration::from_secs(2))).await;
+        assert!(matches!(res, Err(SimulationPoolError::TimedOut)));
+    }
+
+    #[tokio::test]
+    async fn reports_panicking_jobs_as_failed() {
+        let pool = pool(1, 0, 5);
+        assert!(matches!(pool.run(|| panic!("job panicked")).await, Err::<(), _>(SimulationPoolError::Failed)));
+        // The worker isn't lost with the job
+        pool.run(|| ()).await.unwrap();
+    }
+}
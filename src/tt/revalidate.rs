@@ -0,0 +1,273 @@
+// When the simulation backend is updated, stored ghosts may no longer reproduce on it. On startup, every valid
+// time trial that wasn't validated with the current backend version is simulated again; the ones that fail are
+// marked invalid, which keeps them off leaderboards until their owner uploads a run that does reproduce.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use sqlx::types::Uuid;
+use tracing::{info, warn};
+
+use crate::{
+    archive::{ArchiveItemKey, ArchiveItemType},
+    db::{
+        archive::archive_item::ArchiveItem,
+        tt::{revalidation::RevalidationRun, tt_entry::TimeTrialEntry},
+    },
+    state::ThreadSafeState,
+    tt::{
+        SimulationTarget, backend::TimeTrialBackend, get_tt_file_path, get_tt_info,
+        pool::{SimulationPool, SimulationPoolError},
+        read_stage_checkpoints, read_tt_file_at, validate_tt,
+    },
+};
+
+/// How many of the newest time trials are tried when asking the backend for its version.
+const PROBE_CANDIDATES: i64 = 10;
+
+#[derive(serde::Serialize)]
+pub struct RevalidationReport {
+    pub run_id: i32,
+    pub backend_version: i32,
+    /// ISO 8601 format
+    pub started_at: String,
+    /// ISO 8601 format; None if the run hasn't finished
+    pub finished_at: Option<String>,
+    pub checked: i32,
+    pub invalidated: i32,
+    /// Number of invalidated time trials per user
+    pub users: BTreeMap<String, usize>,
+    pub cars: Vec<AffectedItem>,
+    pub stages: Vec<AffectedItem>,
+    pub time_trials: Vec<InvalidatedTimeTrialReport>,
+}
+
+#[derive(serde::Serialize)]
+pub struct AffectedItem {
+    #[serde(flatten)]
+    pub item: ArchiveItemKey,
+    pub invalidated: usize,
+}
+
+#[derive(serde::Serialize)]
+pub struct InvalidatedTimeTrialReport {
+    pub id: String,
+    pub username: String,
+    pub car: ArchiveItemKey,
+    pub stage: ArchiveItemKey,
+    pub ticks: i32,
+    pub reason: Option<String>,
+}
+
+impl RevalidationReport {
+    pub async fn build(pool: &sqlx::PgPool, run: RevalidationRun) -> Result<Self, sqlx::Error> {
+        let invalidated = run.get_invalidated(pool).await?;
+
+        let mut users = BTreeMap::new();
+        let mut cars = BTreeMap::<(String, String), usize>::new();
+        let mut stages = BTreeMap::<(String, String), usize>::new();
+        for tt in &invalidated {
+            *users.entry(tt.username.clone()).or_insert(0) += 1;
+            *cars.entry((tt.car_author.clone(), tt.car_name.clone())).or_insert(0) += 1;
+            *stages.entry((tt.stage_author.clone(), tt.stage_name.clone())).or_insert(0) += 1;
+        }
+        let to_items = |counts: BTreeMap<(String, String), usize>| {
+            counts
+                .into_iter()
+                .map(|((author, name), invalidated)| AffectedItem { item: ArchiveItemKey { author, name }, invalidated })
+                .collect::<Vec<_>>()
+        };
+
+        Ok(RevalidationReport {
+            run_id: run.id,
+            backend_version: run.backend_version,
+            started_at: run.started_at.to_string(),
+            finished_at: run.finished_at.map(|dt| dt.to_string()),
+            checked: run.checked,
+            invalidated: run.invalidated,
+            users,
+            cars: to_items(cars),
+            stages: to_items(stages),
+            time_trials: invalidated
+                .into_iter()
+                .map(|tt| InvalidatedTimeTrialReport {
+                    id: tt.id.to_string(),
+                    car: tt.car(),
+                    stage: tt.stage(),
+                    username: tt.username,
+                    ticks: tt.total_ticks,
+                    reason: tt.invalid_reason,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Re-validates stored time trials if the backend version changed since they were validated.
+/// Returns the report of the run, or None if nothing needed re-validating.
+pub async fn revalidate_time_trials(state: ThreadSafeState) -> Result<Option<RevalidationReport>, String> {
+    let pool = state.db_pool.clone();
+    let root = state.config.filestore.clone();
+    let sim_pool = state.sim_pool.clone();
+    let backend = state.tt_backend.clone();
+
+    let Some(backend_version) = probe_backend_version(&pool, &root, &sim_pool, &backend).await? else {
+        return Ok(None);
+    };
+
+    let candidates = TimeTrialEntry::get_needing_revalidation(&pool, backend_version)
+        .await
+        .map_err(|e| e.to_string())?;
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+
+    info!(backend_version, count = candidates.len(), "re-validating time trials for a new simulation backend version");
+    let run = RevalidationRun::start(&pool, backend_version).await.map_err(|e| e.to_string())?;
+
+    let mut checked = 0;
+    let mut invalidated = 0;
+    for tt in &candidates {
+        let target = match load_target(&pool, tt).await {
+            Ok(target) => target,
+            // Without its car or stage the run can't be replayed any more, so it's as good as failed.
+            Err(TargetError::NotArchived(reason)) => {
+                checked += 1;
+                invalidated += 1;
+                TimeTrialEntry::invalidate(&pool, tt.id, reason, Some(run.id)).await.map_err(|e| e.to_string())?;
+                continue;
+            }
+            Err(TargetError::Failed(e)) => {
+                warn!(tt_id = %tt.id, error = %e, "skipping re-validation of time trial");
+                continue;
+            }
+        };
+        let bytes = match read_tt_file_at(&get_tt_file_path(&root, tt.id)).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!(tt_id = %tt.id, error = %e, "skipping re-validation of time trial: failed to read file");
+                continue;
+            }
+        };
+
+        let job_backend = backend.clone();
+        let result = match run_job(&sim_pool, move || validate_tt(job_backend.as_ref(), &target, &bytes)).await {
+            Ok(result) => result,
+            Err(e) => {
+                warn!(tt_id = %tt.id, error = %e, "skipping re-validation of time trial");
+                continue;
+            }
+        };
+        checked += 1;
+
+        let outcome = match result {
+            Ok((res, _)) if res.elapsed_ticks == tt.total_ticks => Ok(res.checkpoint_ticks),
+            Ok((res, _)) => Err(format!("finishes in {} ticks instead of {}", res.elapsed_ticks, tt.total_ticks)),
+            Err(e) => Err(e),
+        };
+        let updated = match outcome {
+            Ok(checkpoint_ticks) => mark_valid(&pool, tt.id, &checkpoint_ticks, backend_version).await,
+            Err(reason) => {
+                invalidated += 1;
+                TimeTrialEntry::invalidate(&pool, tt.id, &reason, Some(run.id)).await
+            }
+        };
+        updated.map_err(|e| e.to_string())?;
+    }
+
+    RevalidationRun::finish(&pool, run.id, checked, invalidated).await.map_err(|e| e.to_string())?;
+    let run = RevalidationRun::get(&pool, run.id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("revalidation run {} disappeared", run.id))?;
+    let report = RevalidationReport::build(&pool, run).await.map_err(|e| e.to_string())?;
+
+    info!(
+        run_id = report.run_id,
+        checked = report.checked,
+        invalidated = report.invalidated,
+        users = report.users.len(),
+        cars = report.cars.len(),
+        stages = report.stages.len(),
+        "re-validation finished"
+    );
+    Ok(Some(report))
+}
+
+/// Asks the backend for its version. It only reports it alongside a file's info, so it's asked about the newest
+/// stored ghosts, moving on to older ones if a ghost is missing or unreadable. Returns None if there are no ghosts.
+async fn probe_backend_version(
+    pool: &sqlx::PgPool,
+    root: &str,
+    sim_pool: &SimulationPool,
+    backend: &Arc<dyn TimeTrialBackend>,
+) -> Result<Option<i32>, String> {
+    let probes = TimeTrialEntry::get_latest_valid(pool, PROBE_CANDIDATES).await.map_err(|e| e.to_string())?;
+    if probes.is_empty() {
+        return Ok(None);
+    }
+
+    for probe in &probes {
+        let bytes = match read_tt_file_at(&get_tt_file_path(root, probe.id)).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!(tt_id = %probe.id, error = %e, "can't probe the backend version with time trial: failed to read file");
+                continue;
+            }
+        };
+        let probe_backend = backend.clone();
+        match run_job(sim_pool, move || get_tt_info(probe_backend.as_ref(), &bytes)).await {
+            Ok(Ok(info)) => return Ok(Some(info.backend_version)),
+            Ok(Err(e)) | Err(e) => warn!(tt_id = %probe.id, error = %e, "can't probe the backend version with time trial"),
+        }
+    }
+
+    Err(format!("none of the {} newest time trials could be read by the backend", probes.len()))
+}
+
+enum TargetError {
+    /// The car or stage was removed from the archive.
+    NotArchived(&'static str),
+    Failed(String),
+}
+
+// Splits can shift with the backend even when the run still finishes on the same tick. They're stored together with
+// the version, so a run is never marked as checked while still holding the old backend's splits.
+async fn mark_valid(pool: &sqlx::PgPool, tt_id: Uuid, checkpoint_ticks: &[i32], backend_version: i32) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    TimeTrialEntry::set_splits(&mut *tx, tt_id, checkpoint_ticks).await?;
+    TimeTrialEntry::set_backend_version(&mut *tx, tt_id, backend_version).await?;
+    tx.commit().await
+}
+
+async fn load_target(pool: &sqlx::PgPool, tt: &TimeTrialEntry) -> Result<SimulationTarget, TargetError> {
+    let car = ArchiveItem::get_by_key(pool, &tt.car())
+        .await
+        .map_err(|e| TargetError::Failed(e.to_string()))?
+        .filter(|item| item.r#type == ArchiveItemType::Car.to_string())
+        .ok_or(TargetError::NotArchived("car is no longer archived"))?;
+    let stage = ArchiveItem::get_by_key(pool, &tt.stage())
+        .await
+        .map_err(|e| TargetError::Failed(e.to_string()))?
+        .filter(|item| item.r#type == ArchiveItemType::Stage.to_string())
+        .ok_or(TargetError::NotArchived("stage is no longer archived"))?;
+
+    Ok(SimulationTarget {
+        stage_checkpoints: read_stage_checkpoints(&stage.path).await.map_err(TargetError::Failed)?,
+        stage_name: stage.name,
+        car_name: car.name,
+    })
+}
+
+/// Runs a job on the simulation pool. Unlike an upload, there's no client to retry, so a full queue is waited out.
+async fn run_job<T, F>(sim_pool: &SimulationPool, job: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + Clone + 'static,
+{
+    loop {
+        match sim_pool.run(job.clone()).await {
+            Err(SimulationPoolError::QueueFull) => tokio::time::sleep(std::time::Duration::from_secs(1)).await,
+            res => return res.map_err(|e| e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// On-disk format of .timetrial files. Files are stored zstd-compressed behind a small header:
+//
+//     "NFTT" | format (1 byte) | payload
+//
+// where format 0 is the raw replay and format 1 a single zstd frame of it. Files written before the header existed
+// have no header at all, and are read as raw replays.
+
+use std::io;
+
+const MAGIC: &[u8; 4] = b"NFTT";
+const HEADER_LENGTH: usize = MAGIC.len() + 1;
+const COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredFormat {
+    Raw = 0,
+    Zstd = 1,
+}
+
+/// Splits a stored file into its format and payload.
+pub fn split_header(stored: &[u8]) -> io::Result<(StoredFormat, &[u8])> {
+    let Some(payload) = stored.strip_prefix(MAGIC) else {
+        return Ok((StoredFormat::Raw, stored));
+    };
+    match payload.first() {
+        Some(0) => Ok((StoredFormat::Raw, &stored[HEADER_LENGTH..])),
+        Some(1) => Ok((StoredFormat::Zstd, &stored[HEADER_LENGTH..])),
+        Some(f) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown time trial storage format {f}"))),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "truncated time trial storage header")),
+    }
+}
+
+/// Compresses a replay into its stored form.
+pub fn encode(data: &[u8]) -> io::Result<Vec<u8>> {
+    let compressed = zstd::bulk::compress(data, COMPRESSION_LEVEL)?;
+    let mut stored = Vec::with_capacity(HEADER_LENGTH + compressed.len());
+    stored.extend_from_slice(MAGIC);
+    stored.push(StoredFormat::Zstd as u8);
+    stored.extend_from_slice(&compressed);
+    Ok(stored)
+}
+
+/// Returns the replay in a stored file, whatever format it was stored in.
+pub fn decode(stored: &[u8]) -> io::Result<Vec<u8>> {
+    match split_header(stored)? {
+        (StoredFormat::Raw, payload) => Ok(payload.to_vec()),
+        (StoredFormat::Zstd, payload) => zstd::stream::decode_all(payload),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_files_decode_to_the_replay() {
+        let replay = b"replay data ".repeat(100);
+        let stored = encode(&replay).unwrap();
+        assert_eq!(split_header(&stored).unwrap().0, StoredFormat::Zstd);
+        assert!(stored.len() < replay.len());
+        assert_eq!(decode(&stored).unwrap(), replay);
+    }
+
+    #[test]
+    fn files_without_a_header_are_raw_replays() {
+        let replay = b"written before the header existed";
+        assert_eq!(split_header(replay).unwrap(), (StoredFormat::Raw, &replay[..]));
+        assert_eq!(decode(replay).unwrap(), replay);
+    }
+
+    #[test]
+    fn raw_format_payload_is_returned_as_is() {
+        assert_eq!(decode(b"NFTT\x00replay").unwrap(), b"replay");
+    }
+
+    #[test]
+    fn bad_headers_are_rejected() {
+        assert_eq!(decode(b"NFTT\x07replay").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(decode(b"NFTT").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
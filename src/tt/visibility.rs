@@ -0,0 +1,65 @@
+// Who may download a time trial's ghost. Owners can always see their own, and moderators can see everything they
+// might have to review. For anyone else, a ghost entered in an event stays hidden until the event ends, and
+// otherwise the owner's ghost visibility setting applies.
+
+use sqlx::types::Uuid;
+
+use crate::{
+    db::{
+        tt::event::TimeTrialEvent,
+        user_follow::are_friends,
+        user_role::{MODERATOR_ROLES, user_has_role},
+        user_settings::{GhostVisibility, get_ghost_visibility},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GhostAccess {
+    Allowed,
+    /// The owner's visibility setting doesn't include the viewer
+    Restricted,
+    /// The ghost is entered in an event that hasn't ended
+    HiddenUntilEventEnds,
+}
+
+impl GhostAccess {
+    /// Explanation for the viewer
+    pub fn message(&self) -> &'static str {
+        match self {
+            GhostAccess::Allowed => "ghost is visible",
+            GhostAccess::Restricted => "the owner of this ghost has restricted who can see it",
+            GhostAccess::HiddenUntilEventEnds => "this ghost is hidden until the event it was entered in ends",
+        }
+    }
+}
+
+/// Which of a time trial's ghosts is being downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ghost {
+    /// The best run, by time trial ID
+    Best(Uuid),
+    /// A run from the history, by history entry ID
+    History(Uuid),
+}
+
+/// Whether `viewer_id` may download `ghost`, owned by `owner_id`.
+pub async fn check_ghost_access(pool: &sqlx::PgPool, viewer_id: i32, ghost: Ghost, owner_id: i32) -> Result<GhostAccess, sqlx::Error> {
+    if owner_id == viewer_id || user_has_role(pool, viewer_id, MODERATOR_ROLES).await? {
+        return Ok(GhostAccess::Allowed);
+    }
+
+    let in_running_event = match ghost {
+        Ghost::Best(tt_id) => TimeTrialEvent::holds_ghost(pool, tt_id).await?,
+        Ghost::History(history_id) => TimeTrialEvent::holds_history_ghost(pool, history_id).await?,
+    };
+    if in_running_event {
+        return Ok(GhostAccess::HiddenUntilEventEnds);
+    }
+
+    let allowed = match get_ghost_visibility(pool, owner_id).await? {
+        GhostVisibility::Public => true,
+        GhostVisibility::Friends => are_friends(pool, viewer_id, owner_id).await?,
+        GhostVisibility::Private => false,
+    };
+    Ok(if allowed { GhostAccess::Allowed } else { GhostAccess::Restricted })
+}